@@ -2,20 +2,16 @@
 
 use bevy_thing::{levels::*, systems};
 
+/// Without a command the level select menu opens, a level name starts right in that level.
 fn main() {
-    if let Some(command) = std::env::args().nth(1) {
-        match command.as_str() {
-            "level1" => level1::app().run(),
-            "level2" => level2::app().run(),
-            "level3" => level3::app().run(),
-            "level4" => level4::app().run(),
-            "level5" => level5::app().run(),
-            "steering-arcade" => systems::steering::arcade_example(),
-            "steering-rapier" => systems::steering::rapier_example(),
-            "context-map" => systems::context_map::example(),
-            "jabber" => systems::jabber::example(),
-            "texture-atlas-utils" => systems::texture_atlas_utils::example(),
-            _ => (),
-        }
+    let command = std::env::args().nth(1);
+    match command.as_deref() {
+        Some("level5") => level5::app().run(),
+        Some("steering-arcade") => systems::steering::arcade_example(),
+        Some("steering-rapier") => systems::steering::rapier_example(),
+        Some("context-map") => systems::context_map::example(),
+        Some("jabber") => systems::jabber::example(),
+        Some("texture-atlas-utils") => systems::texture_atlas_utils::example(),
+        level => app(level).run(),
     }
 }
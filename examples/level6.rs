@@ -8,9 +8,14 @@ use bevy::{core::Timer, input::system::exit_on_esc_system, prelude::*, utils::Ha
 use bevy_thing::{
     assets::*,
     bevy_rapier_utils::*,
+    collision_layer::CollisionLayer,
     commands_ext::*,
+    components::{Facing, Marker, ProximitySet},
     entities::*,
-    systems::{inventory::*, texture_atlas_utils::*},
+    input::*,
+    replay::*,
+    save::{QuickSave, SavePlugin, Saved},
+    systems::{inventory::*, proximity::ProximityPlugin, texture_atlas_utils::*},
 };
 
 fn app() -> AppBuilder {
@@ -21,24 +26,33 @@ fn app() -> AppBuilder {
         // .add_plugin(RapierRenderPlugin)
         .add_plugin(TextureAtlasUtilsPlugin)
         .add_plugin(bevy_easings::EasingsPlugin)
+        .add_plugin(InputMapPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(ProximityPlugin)
         //
         .add_system(exit_on_esc_system.system())
         //
         .add_startup_system(setup.system())
         .add_system(player_input.system())
         .add_system(player_update.system())
+        .add_system(player_facing.system())
         .add_system(player_animation.system())
         .add_system(sprite_animation_update.system())
         .add_system(y_sort.system())
         .add_system(handle_actions.system())
         .add_system(transfer_item.system())
+        .add_system(world_item_events_system.system())
+        .add_system(pickup_cooldown_system.system())
+        .add_system(item_magnet_system.system())
+        .add_system(held_item_selection_system.system())
+        .add_system(held_item_sprite_system.system())
         .add_system(animation_change.system())
         .add_system(oven_update.system())
-        .add_system(inventory_widget_added.system())
-        .add_system(inventory_widget_selection_system.system())
-        .add_system(inventory_widget_items_system.system())
-        .add_system_to_stage(stage::EVENT, inventory_widget_selection_control.system())
+        .add_plugin(InventoryWidgetPlugin)
+        .add_plugin(JournalPlugin)
+        .add_plugin(SavePlugin)
         .add_event::<Action>()
+        .add_event::<WorldItemEvent>()
         .add_asset::<Item>();
     app
 }
@@ -87,6 +101,7 @@ fn setup(
         cam.transform.scale.y = 0.125;
         cam
     });
+    commands.spawn(CameraUiBundle::default());
 
     // player
 
@@ -99,10 +114,11 @@ fn setup(
 
     let player = commands.entity((
         "Player".to_string(),
+        Saved,
         PlayerMarker,
         YSortMarker,
         PlayerState::Idle,
-        Inventory::default(),
+        Facing(Vec2::new(-1.0, 0.0)),
         Transform::from_translation(Vec3::new(0.0, 16.0, LAYER_0)),
         GlobalTransform::default(),
         SpriteAnimation::new(
@@ -123,9 +139,21 @@ fn setup(
             .user_data(player.to_user_data()),
     );
 
-    let collider = commands.entity((ColliderBuilder::ball(2.0).user_data(player.to_user_data()),));
+    let collider = commands.entity((ColliderBuilder::ball(2.0)
+        .user_data(player.to_user_data())
+        .collision_groups(CollisionLayer::Player.groups()),));
 
-    commands.push_children(player, &[dress, collider]);
+    let sensor = commands.entity((ProximitySet::default(),));
+    commands.insert_one(
+        sensor,
+        ColliderBuilder::ball(6.0)
+            .user_data(sensor.to_user_data())
+            .collision_groups(CollisionLayer::Sensor.groups())
+            .sensor(true),
+    );
+
+    commands.insert_one(player, PlayerSensor(sensor));
+    commands.push_children(player, &[dress, collider, sensor]);
 
     // oven
 
@@ -135,6 +163,7 @@ fn setup(
     });
     let oven = commands.entity((
         "Oven".to_string(),
+        Saved,
         YSortMarker,
         OvenState {
             baking_timer: Timer::new(Duration::from_secs_f32(3.0), false),
@@ -160,7 +189,9 @@ fn setup(
 
     // inventory widget
 
-    commands.entity((
+    let inventory = commands.entity((
+        "Player inventory".to_string(),
+        Saved,
         InventoryWidget {
             tex_atlas: oven_atlas.clone(),
             tex_unselected_index: 18,
@@ -174,8 +205,39 @@ fn setup(
         GlobalTransform::default(),
     ));
 
+    commands.insert_one(player, HeldItem::new(inventory));
+    commands.insert_one(
+        player,
+        ItemMagnet {
+            radius: 4.0,
+            inventory,
+        },
+    );
+
+    // chest inventory widget
+
+    commands.entity((
+        "Chest".to_string(),
+        Saved,
+        InventoryWidget {
+            tex_atlas: oven_atlas.clone(),
+            tex_unselected_index: 18,
+            tex_selected_index: 19,
+            ..Default::default()
+        },
+        Inventory {
+            items: vec![items.fish.clone(), items.fish.clone()],
+        },
+        Transform::from_xyz(0.0, -32.0, LAYER_10),
+        GlobalTransform::default(),
+    ));
+
     commands.insert_resource(atlases);
     commands.insert_resource(items);
+    commands.insert_resource(QuickSave {
+        world_item_z: LAYER_0,
+        ..Default::default()
+    });
 }
 
 struct ItemMarker;
@@ -243,7 +305,7 @@ impl SpriteAnimation {
 }
 
 fn sprite_animation_update(
-    time: Res<Time>,
+    time: Res<GameTime>,
     mut anim_query: Query<Mut<SpriteAnimation>>,
     mut sprite_query: Query<(Mut<Transform>, Mut<TextureAtlasSprite>)>,
 ) {
@@ -264,65 +326,57 @@ fn sprite_animation_update(
 
 struct PlayerMarker;
 
+struct PlayerSensor(Entity);
+
 #[derive(Clone, Debug, PartialEq)]
 enum PlayerState {
     Idle,
     Interact,
     Observe,
+    Drop,
+    Throw,
     Move(Vec2),
 }
 
-fn player_input(keys: Res<Input<KeyCode>>, mut state_query: Query<Mut<PlayerState>>) {
-    let mut movement = Vec2::default();
-    if keys.pressed(KeyCode::W) {
-        movement.y += 1.0;
-    }
-    if keys.pressed(KeyCode::A) {
-        movement.x -= 1.0;
-    }
-    if keys.pressed(KeyCode::S) {
-        movement.y -= 1.0;
-    }
-    if keys.pressed(KeyCode::D) {
-        movement.x += 1.0;
-    }
-    let has_movement = if movement.x != 0.0 || movement.y != 0.0 {
-        movement = movement.normalize();
-        true
-    } else {
-        false
+fn player_input(actions: Res<ActionState>, mut state_query: Query<Mut<PlayerState>>) {
+    let movement = actions.movement();
+    let has_movement = movement != Vec2::zero();
+    let held = |action, state| {
+        if actions.just_released(action) {
+            PlayerState::Idle
+        } else {
+            state
+        }
     };
 
     for mut state in state_query.iter_mut() {
         let new_state = match *state {
             PlayerState::Idle => {
-                if keys.just_pressed(KeyCode::E) {
+                if actions.just_pressed(InputAction::Interact) {
                     PlayerState::Interact
-                } else if keys.just_pressed(KeyCode::F) {
+                } else if actions.just_pressed(InputAction::Observe) {
                     PlayerState::Observe
+                } else if actions.just_pressed(InputAction::Drop) {
+                    PlayerState::Drop
+                } else if actions.just_pressed(InputAction::Throw) {
+                    PlayerState::Throw
                 } else if has_movement {
                     PlayerState::Move(movement)
                 } else {
                     PlayerState::Idle
                 }
             }
-            PlayerState::Interact => {
-                if keys.just_released(KeyCode::E) {
-                    PlayerState::Idle
-                } else {
-                    PlayerState::Interact
-                }
-            }
-            PlayerState::Observe => {
-                if keys.just_released(KeyCode::F) {
-                    PlayerState::Idle
-                } else {
-                    PlayerState::Observe
-                }
-            }
+            PlayerState::Interact => held(InputAction::Interact, PlayerState::Interact),
+            PlayerState::Observe => held(InputAction::Observe, PlayerState::Observe),
+            PlayerState::Drop => held(InputAction::Drop, PlayerState::Drop),
+            PlayerState::Throw => held(InputAction::Throw, PlayerState::Throw),
             PlayerState::Move(_) => {
-                if keys.just_pressed(KeyCode::E) {
+                if actions.just_pressed(InputAction::Interact) {
                     PlayerState::Interact
+                } else if actions.just_pressed(InputAction::Drop) {
+                    PlayerState::Drop
+                } else if actions.just_pressed(InputAction::Throw) {
+                    PlayerState::Throw
                 } else if has_movement {
                     PlayerState::Move(movement)
                 } else {
@@ -339,15 +393,28 @@ fn player_input(keys: Res<Input<KeyCode>>, mut state_query: Query<Mut<PlayerStat
 
 fn player_update(
     mut actions: ResMut<Events<Action>>,
+    mut item_events: ResMut<Events<WorldItemEvent>>,
+    mut observations: ResMut<Events<ObservationEvent>>,
     mut bodies: ResMut<RigidBodySet>,
     player_query: Query<
-        (Entity, &PlayerState, &Transform, &RigidBodyHandleComponent),
+        (
+            Entity,
+            &PlayerState,
+            &Transform,
+            &RigidBodyHandleComponent,
+            &HeldItem,
+            &Facing,
+            &PlayerSensor,
+        ),
         (Changed<PlayerState>, With<PlayerMarker>),
     >,
     mut oven_query: Query<(Entity, &Transform, Mut<OvenState>)>,
+    proximity_query: Query<&ProximitySet>,
+    world_item_query: Query<&WorldItem>,
     items: Res<Items>,
+    item_assets: Res<Assets<Item>>,
 ) {
-    for (player, state, trans, body) in player_query.iter() {
+    for (player, state, trans, body, held, facing, sensor) in player_query.iter() {
         match state {
             PlayerState::Move(dir) => {
                 let movement: Vec2 = *dir * 30.0;
@@ -364,15 +431,75 @@ fn player_update(
 
         match state {
             PlayerState::Interact => {
+                let near_item = proximity_query
+                    .get(sensor.0)
+                    .ok()
+                    .and_then(|near| near.iter().find(|e| world_item_query.get(**e).is_ok()));
+
+                if let Some(item) = near_item {
+                    item_events.send(WorldItemEvent::PickUp {
+                        item: *item,
+                        inventory: held.widget,
+                    });
+                    continue;
+                }
+
                 for (oven, oven_trans, mut oven_state) in oven_query.iter_mut() {
                     if pos(trans).distance_squared(pos(oven_trans)) < 64.0 {
                         // interact with oven
-                        for item in oven_state.interact(&items) {
-                            actions.send(Action::TransferItem(item, oven, player));
+                        match oven_state.interact(&items, held.item.as_ref()) {
+                            Some(OvenTransfer::Put(item)) => {
+                                actions.send(Action::TransferItem(item, held.widget, oven))
+                            }
+                            Some(OvenTransfer::Take(item)) => {
+                                actions.send(Action::TransferItem(item, oven, held.widget))
+                            }
+                            None => {}
                         }
                     }
                 }
             }
+            PlayerState::Observe => {
+                let near_items = proximity_query
+                    .get(sensor.0)
+                    .ok()
+                    .into_iter()
+                    .flat_map(|near| near.iter())
+                    .filter_map(|e| world_item_query.get(*e).ok())
+                    .filter_map(|item| item_assets.get(&item.0));
+
+                for item in near_items {
+                    observations.send(ObservationEvent {
+                        observer: player,
+                        subject: Subject::Item(item.name.to_string()),
+                    });
+                }
+
+                for (_, oven_trans, _) in oven_query.iter_mut() {
+                    if pos(trans).distance_squared(pos(oven_trans)) < 64.0 {
+                        observations.send(ObservationEvent {
+                            observer: player,
+                            subject: Subject::Marker(Marker::Oven),
+                        });
+                    }
+                }
+            }
+            PlayerState::Drop | PlayerState::Throw => {
+                let speed = if *state == PlayerState::Drop {
+                    10.0
+                } else {
+                    120.0
+                };
+
+                for item in held.item.clone() {
+                    item_events.send(WorldItemEvent::Drop {
+                        item,
+                        from: held.widget,
+                        position: pos(trans).extend(LAYER_0),
+                        velocity: facing.0 * speed,
+                    });
+                }
+            }
             _ => {}
         }
     }
@@ -489,25 +616,37 @@ fn transfer_item(
     }
 }
 
+fn player_facing(mut query: Query<(&PlayerState, Mut<Facing>), Changed<PlayerState>>) {
+    for (state, mut facing) in query.iter_mut() {
+        if let PlayerState::Move(movement) = state {
+            for new_facing in Facing::from_movement(*movement) {
+                if *facing != new_facing {
+                    *facing = new_facing;
+                }
+            }
+        }
+    }
+}
+
 fn player_animation(
     mut bodies: ResMut<RigidBodySet>,
-    mut player_query: Query<(Mut<SpriteAnimation>, &RigidBodyHandleComponent), With<PlayerMarker>>,
+    mut player_query: Query<
+        (Mut<SpriteAnimation>, &RigidBodyHandleComponent, &Facing),
+        With<PlayerMarker>,
+    >,
 ) {
-    for (mut anim, body) in player_query.iter_mut() {
+    for (mut anim, body, facing) in player_query.iter_mut() {
+        for flip_x in facing.flip_x() {
+            if anim.flip_x != flip_x {
+                anim.flip_x = flip_x;
+            }
+        }
+
         for body in bodies.get_mut(body.handle()) {
             let linvel = body.linvel();
 
             if linvel.magnitude_squared() > 0.5 {
                 anim.set("walking");
-
-                for x in linvel.get(0) {
-                    if *x > 0.0 {
-                        anim.flip_x = true;
-                    }
-                    if *x < 0.0 {
-                        anim.flip_x = false;
-                    }
-                }
             } else {
                 anim.set("standing");
             }
@@ -7,10 +7,13 @@ pub use bevy_rapier2d::{
         RigidBodyHandleComponent,
     },
     rapier::{
-        dynamics::{BodyStatus, RigidBody, RigidBodyBuilder, RigidBodyHandle, RigidBodySet},
+        dynamics::{
+            BodyStatus, IntegrationParameters, RigidBody, RigidBodyBuilder, RigidBodyHandle,
+            RigidBodySet,
+        },
         geometry::{
-            Collider, ColliderBuilder, ColliderHandle, ColliderSet, Proximity, ProximityEvent,
-            ShapeType,
+            Collider, ColliderBuilder, ColliderHandle, ColliderSet, ContactEvent,
+            InteractionGroups, Proximity, ProximityEvent, ShapeType,
         },
         math::Isometry,
     },
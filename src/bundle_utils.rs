@@ -1,6 +1,8 @@
 use bevy::{ecs::DynamicBundle, prelude::*};
 use bevy_rapier2d::rapier::{dynamics::RigidBodyBuilder, geometry::ColliderBuilder};
 
+use crate::collision_layer::CollisionLayer;
+
 pub fn sprite_bundle(
     texture_atlas: Handle<TextureAtlas>,
     index: u32,
@@ -18,6 +20,6 @@ pub fn static_tile_physics_bundle(entity: Entity, transform: Transform) -> impl
         RigidBodyBuilder::new_static()
             .translation(transform.translation.x, transform.translation.y)
             .user_data(entity.to_bits() as u128),
-        ColliderBuilder::cuboid(8.0, 8.0),
+        ColliderBuilder::cuboid(8.0, 8.0).collision_groups(CollisionLayer::Wall.groups()),
     )
 }
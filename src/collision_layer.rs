@@ -0,0 +1,89 @@
+use crate::bevy_rapier_utils::InteractionGroups;
+
+/// Named rapier collision groups, which layers touch each other is declared
+/// in `COLLIDES` and `SENSES` instead of hand written masks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CollisionLayer {
+    Player,
+    NPC,
+    Item,
+    Wall,
+    Sensor,
+    Projectile,
+}
+
+/// Pairs of layers whose colliders bump into each other, in both directions.
+const COLLIDES: &[(CollisionLayer, CollisionLayer)] = {
+    use CollisionLayer::*;
+    &[
+        (Player, Player),
+        (Player, NPC),
+        (Player, Item),
+        (Player, Wall),
+        (NPC, NPC),
+        (NPC, Wall),
+        (Item, Item),
+        (Item, Wall),
+        (Projectile, Player),
+        (Projectile, NPC),
+        (Projectile, Wall),
+    ]
+};
+
+/// Pairs of a sensing layer and a layer its sensors notice.
+const SENSES: &[(CollisionLayer, CollisionLayer)] = {
+    use CollisionLayer::*;
+    &[
+        (Sensor, Player),
+        (Sensor, NPC),
+        (Sensor, Item),
+        (Sensor, Wall),
+    ]
+};
+
+#[test]
+fn collision_matrix() {
+    use CollisionLayer::*;
+
+    assert!(Player.interacts(Wall));
+    assert!(Wall.interacts(Player));
+    assert!(Item.interacts(Item));
+    assert!(!NPC.interacts(Item));
+    assert!(!Wall.interacts(Wall));
+    assert!(Sensor.interacts(Item));
+    assert!(Item.interacts(Sensor));
+    assert!(!Sensor.interacts(Sensor));
+    assert!(!Sensor.interacts(Projectile));
+}
+
+impl CollisionLayer {
+    pub fn bit(self) -> u16 {
+        1 << self as u16
+    }
+
+    /// The bits of all `layers`, like for `ProximityFilter::groups`.
+    pub fn bits(layers: &[CollisionLayer]) -> u16 {
+        layers.iter().fold(0, |bits, it| bits | it.bit())
+    }
+
+    /// Bits of all layers this layer collides with or senses, or is sensed by.
+    pub fn mask(self) -> u16 {
+        COLLIDES
+            .iter()
+            .chain(SENSES.iter())
+            .fold(0, |mask, &(a, b)| match (a == self, b == self) {
+                (true, _) => mask | b.bit(),
+                (_, true) => mask | a.bit(),
+                _ => mask,
+            })
+    }
+
+    pub fn groups(self) -> InteractionGroups {
+        InteractionGroups::new(self.bit(), self.mask())
+    }
+
+    /// Whether rapier looks at pairs of colliders of these layers, like `InteractionGroups::test`.
+    pub fn interacts(self, other: CollisionLayer) -> bool {
+        self.bit() & other.mask() != 0 && other.bit() & self.mask() != 0
+    }
+}
@@ -23,4 +23,57 @@ pub enum Marker {
     Torch,
 }
 
+impl Marker {
+    pub fn parse(name: &str) -> Option<Self> {
+        use Marker::*;
+        let marker = match name {
+            "Wall" => Wall,
+            "Chair" => Chair,
+            "Table" => Table,
+            "Window" => Window,
+            "Door" => Door,
+            "Bookshelf" => Bookshelf,
+            "Mirror" => Mirror,
+            "Oven" => Oven,
+            "Bed" => Bed,
+            "Dirt" => Dirt,
+            "RandomTree" => RandomTree,
+            "PlayerSpawn" => PlayerSpawn,
+            "Player" => Player,
+            "Torch" => Torch,
+            _ => return None,
+        };
+        Some(marker)
+    }
+}
+
 pub type ProximitySet = HashSet<Entity>;
+
+/// The axis direction an entity looks at, follows the last movement.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Facing(pub Vec2);
+
+impl Default for Facing {
+    fn default() -> Self {
+        Facing(Vec2::unit_y())
+    }
+}
+
+impl Facing {
+    /// The dominant axis of `movement`, none when there is hardly any movement.
+    pub fn from_movement(movement: Vec2) -> Option<Self> {
+        if movement.length_squared() < 0.01 {
+            None
+        } else if movement.x.abs() > movement.y.abs() {
+            Some(Facing(Vec2::new(movement.x.signum(), 0.0)))
+        } else {
+            Some(Facing(Vec2::new(0.0, movement.y.signum())))
+        }
+    }
+
+    /// Sprites look left, so they are flipped when facing right.
+    /// `None` when facing up or down, keep the last flip then.
+    pub fn flip_x(&self) -> Option<bool> {
+        (self.0.x != 0.0).then_some(self.0.x > 0.0)
+    }
+}
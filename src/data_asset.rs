@@ -0,0 +1,89 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    asset::{Asset, AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+/// A resource that is loaded from a text file in `assets` and replaced whenever
+/// the file is loaded or changes, until then it keeps its default.
+pub trait DataAsset: Asset + Clone + Default {
+    /// Loaded on startup, relative to `assets`.
+    const PATH: &'static str;
+    const EXTENSIONS: &'static [&'static str];
+
+    fn parse(text: &str) -> Result<Self, anyhow::Error>;
+}
+
+/// Adds `T` as a resource and as an asset, with its loader and the sync system.
+pub struct DataAssetPlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for DataAssetPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: DataAsset> Plugin for DataAssetPlugin<T> {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<T>()
+            .add_asset::<T>()
+            .init_asset_loader::<DataAssetLoader<T>>()
+            .add_startup_system(load_data_asset::<T>.system())
+            .add_system(sync_data_asset_system::<T>.system());
+    }
+}
+
+struct DataAssetHandle<T: DataAsset>(Handle<T>);
+
+fn load_data_asset<T: DataAsset>(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(DataAssetHandle::<T>(asset_server.load(T::PATH)));
+}
+
+fn sync_data_asset_system<T: DataAsset>(
+    handle: Res<DataAssetHandle<T>>,
+    assets: Res<Assets<T>>,
+    mut resource: ResMut<T>,
+    mut event_reader: Local<EventReader<AssetEvent<T>>>,
+    events: Res<Events<AssetEvent<T>>>,
+) {
+    for event in event_reader.iter(&events) {
+        match event {
+            AssetEvent::Created { handle: it } | AssetEvent::Modified { handle: it } => {
+                if *it == handle.0 {
+                    for loaded in assets.get(it) {
+                        *resource = loaded.clone();
+                    }
+                }
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}
+
+pub struct DataAssetLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for DataAssetLoader<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: DataAsset> AssetLoader for DataAssetLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset = T::parse(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        T::EXTENSIONS
+    }
+}
@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+
+use crate::{
+    commands_ext::CommandsExt,
+    entities::InventoryWidget,
+    systems::inventory::{Inventory, Item, ItemKind},
+};
+
+/// The item in hand, defined by the selected slot of an inventory widget.
+pub struct HeldItem {
+    pub widget: Entity,
+    pub item: Option<ItemKind>,
+    pub offset: Vec3,
+    sprite: Option<Entity>,
+}
+
+impl HeldItem {
+    pub fn new(widget: Entity) -> Self {
+        Self {
+            widget,
+            item: None,
+            offset: Vec3::new(3.0, 1.0, 0.1),
+            sprite: None,
+        }
+    }
+}
+
+pub fn held_item_selection_system(
+    mut held_query: Query<Mut<HeldItem>>,
+    widget_query: Query<(&InventoryWidget, &Inventory)>,
+) {
+    for mut held in held_query.iter_mut() {
+        let item = widget_query
+            .get(held.widget)
+            .ok()
+            .and_then(|(widget, inventory)| inventory.get(widget.selection?).cloned());
+
+        if held.item != item {
+            held.item = item;
+        }
+    }
+}
+
+pub fn held_item_sprite_system(
+    commands: &mut Commands,
+    items: Res<Assets<Item>>,
+    mut held_query: Query<(Entity, Mut<HeldItem>), Changed<HeldItem>>,
+) {
+    for (entity, mut held) in held_query.iter_mut() {
+        for sprite in held.sprite.take() {
+            commands.despawn_recursive(sprite);
+        }
+
+        for item in held.item.as_ref().and_then(|it| items.get(it)) {
+            let sprite = commands
+                .spawn(item.sprite_sheet_bundle())
+                .with(Transform {
+                    translation: held.offset,
+                    scale: Vec3::new(0.5, 0.5, 1.0),
+                    ..Default::default()
+                })
+                .unwrap_entity();
+
+            commands.push_children(entity, &[sprite]);
+            held.sprite = Some(sprite);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use bevy::{prelude::*, render::camera::Camera};
+
+use crate::{
+    commands_ext::CommandsExt,
+    components::{Marker, ProximitySet},
+    entities::{player::Player, OvenState},
+    input::{InputAction, InputMap, InputSource},
+    interactions::{InteractionKind, InteractionRegistry, Openable},
+    systems::inventory::{Inventory, Items},
+    utils::{camera_2d_transform, world_to_screen},
+};
+
+/// Shows "E: Open door" or "E: Bake fish" above the best interactable in front of each player
+/// and tints it, hidden when there is nothing to interact with.
+pub struct InteractionPromptPlugin;
+
+impl Plugin for InteractionPromptPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(interaction_prompt_added.system())
+            .add_system(interaction_prompt_target_system.system())
+            .add_system(interaction_prompt_highlight_system.system())
+            .add_system(interaction_prompt_text_system.system());
+    }
+}
+
+const HIGHLIGHT_COLOR: Color = Color::rgb(1.0, 1.0, 0.6);
+const PROMPT_OFFSET: f32 = 12.0;
+
+pub struct InteractionPrompt {
+    text: Entity,
+    pub target: Option<Entity>,
+}
+
+/// The sprite color before the prompt tinted it.
+struct Highlighted(Color);
+
+fn interaction_prompt_added(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<Entity, Added<Player>>,
+) {
+    for entity in query.iter() {
+        let text = commands.entity(TextBundle {
+            text: Text {
+                value: String::new(),
+                font: asset_server.load("FiraSans-Bold.ttf"),
+                style: TextStyle {
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                    ..Default::default()
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        commands.insert_one(entity, InteractionPrompt { text, target: None });
+    }
+}
+
+/// Picks the interactable with the highest priority, the closest one on ties.
+pub fn interaction_prompt_target_system(
+    registry: Res<InteractionRegistry>,
+    mut player_query: Query<(&Player, &GlobalTransform, Mut<InteractionPrompt>)>,
+    proximity_query: Query<&ProximitySet>,
+    target_query: Query<(&Marker, &GlobalTransform)>,
+) {
+    for (player, player_trans, mut prompt) in player_query.iter_mut() {
+        let near = player
+            .forward_sensor()
+            .and_then(|sensor| proximity_query.get(sensor).ok());
+
+        let target = near.and_then(|near| {
+            near.iter()
+                .filter_map(|entity| {
+                    let (marker, trans) = target_query.get(*entity).ok()?;
+                    let kind = registry.get(*marker)?;
+                    let distance = trans.translation.distance_squared(player_trans.translation);
+                    if !distance.is_finite() {
+                        // could not be ordered below
+                        return None;
+                    }
+                    Some((*entity, kind.priority(), distance))
+                })
+                .max_by(|(_, a_priority, a_distance), (_, b_priority, b_distance)| {
+                    a_priority
+                        .cmp(b_priority)
+                        .then(b_distance.partial_cmp(a_distance).unwrap())
+                })
+                .map(|(entity, _, _)| entity)
+        });
+
+        if prompt.target != target {
+            prompt.target = target;
+        }
+    }
+}
+
+/// Tints the targets of all prompts and restores the color of former targets,
+/// the alpha is left alone as doors use it to show they are open.
+pub fn interaction_prompt_highlight_system(
+    commands: &mut Commands,
+    prompt_query: Query<&InteractionPrompt>,
+    changed_query: Query<&InteractionPrompt, Changed<InteractionPrompt>>,
+    mut sprite_query: Query<(Entity, Mut<TextureAtlasSprite>, Option<&Highlighted>)>,
+) {
+    if changed_query.iter().next().is_none() {
+        return;
+    }
+
+    let targets: Vec<Entity> = prompt_query.iter().filter_map(|it| it.target).collect();
+
+    for (entity, mut sprite, highlighted) in sprite_query.iter_mut() {
+        match (targets.contains(&entity), highlighted) {
+            (true, None) => {
+                commands.insert_one(entity, Highlighted(sprite.color));
+                let alpha = sprite.color.a();
+                sprite.color = HIGHLIGHT_COLOR;
+                sprite.color.set_a(alpha);
+            }
+            (false, Some(Highlighted(color))) => {
+                let alpha = sprite.color.a();
+                sprite.color = *color;
+                sprite.color.set_a(alpha);
+                commands.remove_one::<Highlighted>(entity);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn interaction_prompt_text_system(
+    windows: Res<Windows>,
+    map: Res<InputMap>,
+    registry: Res<InteractionRegistry>,
+    items: Res<Items>,
+    camera_query: Query<(&Camera, &Transform)>,
+    prompt_query: Query<(&InteractionPrompt, Option<&InputSource>, Option<&Inventory>)>,
+    target_query: Query<(
+        &Marker,
+        &GlobalTransform,
+        Option<&Openable>,
+        Option<&OvenState>,
+    )>,
+    mut text_query: Query<(Mut<Text>, Mut<Style>)>,
+) {
+    let window = windows.get_primary();
+    let camera_transform = camera_2d_transform(&camera_query);
+
+    for (prompt, source, inventory) in prompt_query.iter() {
+        let held = inventory.and_then(|it| it.items.last());
+        let target = prompt
+            .target
+            .and_then(|target| target_query.get(target).ok())
+            .and_then(|(marker, trans, openable, oven)| {
+                let kind = registry.get(*marker)?;
+                let source = source.cloned().unwrap_or(InputSource::Any);
+                let key = map.binding_name(InputAction::Interact, source)?;
+                let action = oven.map(|oven| oven.prompt(&items, held));
+                let label = prompt_label(&key, kind, *marker, openable, action);
+                Some((label, trans.translation.truncate()))
+            });
+
+        if let Ok((mut text, mut style)) = text_query.get_mut(prompt.text) {
+            let (label, position) = match target {
+                Some(target) => target,
+                None => {
+                    if !text.value.is_empty() {
+                        text.value.clear();
+                    }
+                    continue;
+                }
+            };
+
+            if text.value != label {
+                text.value = label;
+            }
+
+            for (window, camera_transform) in window.zip(camera_transform) {
+                let world = position + Vec2::new(0.0, PROMPT_OFFSET);
+                let screen = world_to_screen(window, world, camera_transform);
+                style.position.left = Val::Px(screen.x);
+                style.position.bottom = Val::Px(screen.y);
+            }
+        }
+    }
+}
+
+fn prompt_label(
+    key: &str,
+    kind: &InteractionKind,
+    marker: Marker,
+    openable: Option<&Openable>,
+    action: Option<&str>,
+) -> String {
+    if let Some(action) = action {
+        return format!("{}: {}", key, action);
+    }
+
+    let verb = match (kind, openable) {
+        (InteractionKind::Toggle, Some(openable)) if openable.open => "Close",
+        _ => kind.verb(),
+    };
+    let name = format!("{:?}", marker).to_lowercase();
+    format!("{}: {} {}", key, verb, name)
+}
@@ -1,20 +1,52 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::camera::Camera};
 
 use crate::{
     commands_ext::CommandsExt,
-    systems::inventory::{Inventory, Item},
+    input::{ActionState, InputCapture},
+    systems::inventory::{Inventory, Item, ItemKind},
+    utils::{camera_2d_transform, screen_to_world},
 };
 
+pub struct InventoryWidgetPlugin;
+
+impl Plugin for InventoryWidgetPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<InventoryPointer>()
+            .add_startup_system(inventory_tooltip_setup.system())
+            .add_system(inventory_widget_added.system())
+            .add_system(inventory_widget_pointer_system.system())
+            .add_system(inventory_widget_selection_system.system())
+            .add_system(inventory_widget_items_system.system())
+            .add_system(inventory_tooltip_system.system())
+            .add_system(inventory_widget_selection_control.system());
+    }
+}
+
 #[derive(Default)]
 pub struct InventoryWidget {
     pub slots: Vec<Entity>,
-    pub items: Vec<Entity>,
+    pub items: Vec<Option<(ItemKind, Entity)>>,
     pub selection: Option<usize>,
+    pub hover: Option<usize>,
     pub tex_atlas: Handle<TextureAtlas>,
     pub tex_unselected_index: u32,
     pub tex_selected_index: u32,
 }
 
+const SLOT_HALF_SIZE: f32 = 3.5;
+const SLOT_HOVER_COLOR: Color = Color::rgb(1.0, 1.0, 0.6);
+
+/// Mouse state shared by all inventory widgets, a slot is addressed by widget entity and index.
+#[derive(Default)]
+pub struct InventoryPointer {
+    pub cursor: Option<Vec2>,
+    pub hover: Option<(Entity, usize)>,
+    pub drag: Option<(Entity, usize)>,
+    drag_sprite: Option<Entity>,
+}
+
+pub struct InventoryTooltip(Entity);
+
 pub fn inventory_widget_added(
     commands: &mut Commands,
     mut query: Query<(Entity, Mut<InventoryWidget>), Added<InventoryWidget>>,
@@ -38,33 +70,21 @@ pub fn inventory_widget_added(
 }
 
 pub fn inventory_widget_selection_control(
-    keys: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     mut widget_query: Query<Mut<InventoryWidget>>,
 ) {
-    let slot_selection = [
-        KeyCode::Key1,
-        KeyCode::Key2,
-        KeyCode::Key3,
-        KeyCode::Key4,
-        KeyCode::Key5,
-        KeyCode::Key6,
-        KeyCode::Key7,
-        KeyCode::Key8,
-    ]
-    .iter()
-    .map(|c| keys.just_pressed(*c))
-    .enumerate()
-    .filter_map(|(index, pressed)| pressed.then_some(index))
-    .last();
-
-    let selection = if keys.just_pressed(KeyCode::Key0) {
-        Some(None)
-    } else {
-        slot_selection.map(Some)
-    };
-
-    for selection in selection {
-        for mut widget in widget_query.iter_mut() {
+    let cycle = actions.slot_cycle();
+
+    for mut widget in widget_query.iter_mut() {
+        let slots = widget.slots.len() as isize;
+        let selection = actions.slot_selection().or_else(|| {
+            (cycle != 0 && slots > 0).then(|| {
+                let current = widget.selection.map(|it| it as isize).unwrap_or(-1);
+                Some((current + cycle).rem_euclid(slots) as usize)
+            })
+        });
+
+        for selection in selection {
             if widget.selection != selection {
                 widget.selection = selection;
             }
@@ -72,6 +92,137 @@ pub fn inventory_widget_selection_control(
     }
 }
 
+/// Hover, click to select and drag and drop between slots of any widgets.
+/// A tool that captures the input takes the pointer, as if it left the window.
+pub fn inventory_widget_pointer_system(
+    commands: &mut Commands,
+    windows: Res<Windows>,
+    mouse: Res<Input<MouseButton>>,
+    capture: Res<InputCapture>,
+    items: Res<Assets<Item>>,
+    mut pointer: ResMut<InventoryPointer>,
+    camera_query: Query<(&Camera, &Transform)>,
+    mut widget_query: Query<(Entity, Mut<InventoryWidget>)>,
+    slot_query: Query<&GlobalTransform>,
+    mut inventory_query: Query<Mut<Inventory>>,
+    mut transform_query: Query<Mut<Transform>>,
+) {
+    let window = windows.get_primary().filter(|_| !capture.active);
+    let cursor = window.and_then(|window| {
+        let cursor = window.cursor_position()?;
+        let camera_transform = camera_2d_transform(&camera_query)?;
+        Some((cursor, screen_to_world(window, cursor, camera_transform)))
+    });
+
+    pointer.cursor = cursor.map(|(screen, _)| screen);
+    pointer.hover = None;
+
+    for (_, world) in cursor {
+        for (entity, widget) in widget_query.iter_mut() {
+            for (index, slot) in widget.slots.iter().enumerate() {
+                for slot_trans in slot_query.get(*slot) {
+                    let diff = (world - slot_trans.translation.truncate()).abs();
+                    let half_size = SLOT_HALF_SIZE * slot_trans.scale.truncate().abs();
+                    if diff.x < half_size.x && diff.y < half_size.y {
+                        pointer.hover = Some((entity, index));
+                    }
+                }
+            }
+        }
+    }
+
+    for (entity, mut widget) in widget_query.iter_mut() {
+        let hover = pointer
+            .hover
+            .and_then(|(it, index)| (it == entity).then_some(index));
+        if widget.hover != hover {
+            widget.hover = hover;
+        }
+    }
+
+    if mouse.just_pressed(MouseButton::Left) {
+        for (entity, index) in pointer.hover {
+            for (_, mut widget) in widget_query.get_mut(entity) {
+                if widget.selection != Some(index) {
+                    widget.selection = Some(index);
+                }
+            }
+
+            let item = inventory_query
+                .get_mut(entity)
+                .ok()
+                .and_then(|inventory| inventory.get(index).cloned());
+
+            for item in item.and_then(|it| items.get(it)) {
+                pointer.drag = Some((entity, index));
+                pointer.drag_sprite = Some(
+                    commands
+                        .spawn(item.sprite_sheet_bundle())
+                        .with(Transform::from_xyz(0.0, 0.0, 999.0))
+                        .unwrap_entity(),
+                );
+            }
+        }
+    }
+
+    for (sprite, (_, world)) in pointer.drag_sprite.zip(cursor) {
+        for mut trans in transform_query.get_mut(sprite) {
+            trans.translation.x = world.x;
+            trans.translation.y = world.y;
+        }
+    }
+
+    if mouse.just_released(MouseButton::Left) {
+        for sprite in pointer.drag_sprite.take() {
+            commands.despawn_recursive(sprite);
+        }
+
+        for (from, to) in pointer.drag.take().zip(pointer.hover) {
+            move_slot_item(from, to, &widget_query, &mut inventory_query);
+
+            for (_, mut widget) in widget_query.get_mut(to.0) {
+                widget.selection = Some(to.1);
+            }
+        }
+    }
+}
+
+fn move_slot_item(
+    (from, from_index): (Entity, usize),
+    (to, to_index): (Entity, usize),
+    widget_query: &Query<(Entity, Mut<InventoryWidget>)>,
+    inventory_query: &mut Query<Mut<Inventory>>,
+) {
+    if from == to {
+        for mut inventory in inventory_query.get_mut(from) {
+            inventory.move_item(from_index, to_index);
+        }
+        return;
+    }
+
+    let capacity = widget_query
+        .get_component::<InventoryWidget>(to)
+        .map(|widget| widget.slots.len())
+        .unwrap_or(0);
+    let has_room = inventory_query
+        .get_mut(to)
+        .map(|inventory| inventory.items.len() < capacity)
+        .unwrap_or(false);
+
+    if has_room {
+        let item = inventory_query
+            .get_mut(from)
+            .ok()
+            .and_then(|mut inventory| inventory.remove(from_index));
+
+        for item in item {
+            for mut inventory in inventory_query.get_mut(to) {
+                inventory.insert(to_index, item.clone());
+            }
+        }
+    }
+}
+
 pub fn inventory_widget_selection_system(
     widget_query: Query<&InventoryWidget, Changed<InventoryWidget>>,
     mut sprite_query: Query<(Mut<TextureAtlasSprite>, Mut<Transform>)>,
@@ -79,46 +230,120 @@ pub fn inventory_widget_selection_system(
     for widget in widget_query.iter() {
         for (index, slot) in widget.slots.iter().enumerate() {
             let selected = Some(index) == widget.selection;
+            let hovered = Some(index) == widget.hover;
             let tex_index = if selected {
                 widget.tex_selected_index
             } else {
                 widget.tex_unselected_index
             };
-
-            // TODO slot should keep a semantic state
-            // such that sprite.index and translation.z can be derived from that
-            // Model View distinction!
+            let color = if hovered {
+                SLOT_HOVER_COLOR
+            } else {
+                Color::WHITE
+            };
+            let z = if selected { 0.1 } else { 0.0 };
 
             for (mut sprite, mut trans) in sprite_query.get_mut(*slot) {
-                if sprite.index != tex_index {
+                if sprite.index != tex_index || sprite.color != color {
                     sprite.index = tex_index;
-                    trans.translation.z += if selected { 0.1 } else { -0.1 };
+                    sprite.color = color;
+                }
+                if trans.translation.z != z {
+                    trans.translation.z = z;
                 }
             }
         }
     }
 }
 
+/// Only respawns the item sprites of slots whose item changed,
+/// and drops the selection once its slot runs empty.
 pub fn inventory_widget_items_system(
     commands: &mut Commands,
     items: Res<Assets<Item>>,
     mut widget_query: Query<(Mut<InventoryWidget>, &Inventory), Changed<Inventory>>,
 ) {
     for (mut widget, inventory) in widget_query.iter_mut() {
-        for item in widget.items.drain(0..) {
-            commands.despawn_recursive(item);
-        }
+        let widget = &mut *widget;
+        widget.items.resize(widget.slots.len(), None);
+
+        for (index, slot) in widget.slots.iter().enumerate() {
+            let wanted = inventory.get(index);
+            let current = widget.items[index].as_ref().map(|(kind, _)| kind);
+            if wanted == current {
+                continue;
+            }
+
+            for (_, item_entity) in widget.items[index].take() {
+                commands.despawn_recursive(item_entity);
+            }
+
+            for kind in wanted {
+                for item in items.get(kind) {
+                    let item_entity = commands
+                        .spawn(item.sprite_sheet_bundle())
+                        .with(Transform::from_xyz(0.0, 0.0, 0.1))
+                        .unwrap_entity();
 
-        for (index, item) in inventory.items.iter().enumerate() {
-            for item in items.get(item) {
-                let item_entity = commands
-                    .spawn(item.sprite_sheet_bundle())
-                    .with(Transform::from_xyz(0.0, 0.0, 0.1))
-                    .unwrap_entity();
+                    widget.items[index] = Some((kind.clone(), item_entity));
+                    commands.push_children(*slot, &[item_entity]);
+                }
+            }
+        }
 
-                widget.items.push(item_entity);
-                commands.push_children(widget.slots[index], &[item_entity]);
+        if let Some(selection) = widget.selection {
+            if inventory.get(selection).is_none() {
+                widget.selection = None;
             }
         }
     }
 }
+
+fn inventory_tooltip_setup(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    let tooltip = commands.entity(TextBundle {
+        text: Text {
+            value: String::new(),
+            font: asset_server.load("FiraSans-Bold.ttf"),
+            style: TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        },
+        style: Style {
+            position_type: PositionType::Absolute,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    commands.insert_resource(InventoryTooltip(tooltip));
+}
+
+/// Shows the name of the hovered item next to the cursor.
+pub fn inventory_tooltip_system(
+    tooltip: Res<InventoryTooltip>,
+    pointer: Res<InventoryPointer>,
+    items: Res<Assets<Item>>,
+    inventory_query: Query<&Inventory>,
+    mut text_query: Query<(Mut<Text>, Mut<Style>)>,
+) {
+    let name = pointer
+        .hover
+        .filter(|_| pointer.drag.is_none())
+        .and_then(|(widget, index)| inventory_query.get(widget).ok()?.get(index).cloned())
+        .and_then(|kind| items.get(kind))
+        .map(|item| item.name.replace('_', " "))
+        .unwrap_or_default();
+
+    if let Ok((mut text, mut style)) = text_query.get_mut(tooltip.0) {
+        if text.value != name {
+            text.value = name;
+        }
+
+        for cursor in pointer.cursor {
+            style.position.left = Val::Px(cursor.x + 12.0);
+            style.position.bottom = Val::Px(cursor.y + 12.0);
+        }
+    }
+}
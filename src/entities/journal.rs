@@ -0,0 +1,310 @@
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+
+use crate::{
+    commands_ext::CommandsExt,
+    components::Marker,
+    data_asset::{DataAsset, DataAssetPlugin},
+    input::{ActionState, InputAction},
+    replay::GameTime,
+};
+
+/// Observations end up in the `Journal`, shown as a caption when they happen
+/// and listed on a page toggled with the `Journal` action.
+/// `Caption` events show a line in the same place without recording it.
+/// Needs `GameTime`, see `ReplayPlugin`.
+pub struct JournalPlugin;
+
+impl Plugin for JournalPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Journal>()
+            .add_plugin(DataAssetPlugin::<Descriptions>::default())
+            .add_event::<ObservationEvent>()
+            .add_event::<Caption>()
+            .add_startup_system(journal_setup.system())
+            .add_system(journal_observation_system.system())
+            .add_system(caption_event_system.system())
+            .add_system(journal_caption_system.system())
+            .add_system(journal_page_system.system());
+    }
+}
+
+#[test]
+fn parse_descriptions() {
+    let descriptions = Descriptions::parse(
+        "# comment\n\
+         Door = A heavy door.\n\
+         Item baked_fish = Smells good.\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        descriptions.get(&Subject::Marker(Marker::Door)),
+        Some("A heavy door.")
+    );
+    assert_eq!(
+        descriptions.get(&Subject::Item("baked_fish".into())),
+        Some("Smells good.")
+    );
+    assert_eq!(descriptions.get(&Subject::Marker(Marker::Bed)), None);
+    assert!(Descriptions::parse("Dragon = Big.").is_err());
+}
+
+pub const DESCRIPTIONS_PATH: &'static str = "game.descriptions";
+
+const CAPTION_SECONDS: f32 = 3.0;
+
+/// Something that can be observed, either a marked entity or an item by name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subject {
+    Marker(Marker),
+    Item(String),
+}
+
+impl Subject {
+    pub fn name(&self) -> String {
+        match self {
+            Subject::Marker(marker) => format!("{:?}", marker).to_lowercase(),
+            Subject::Item(name) => name.replace('_', " "),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObservationEvent {
+    pub observer: Entity,
+    pub subject: Subject,
+}
+
+/// A line to show in the caption, like the text of a sign or the outcome of a save.
+#[derive(Debug, Clone)]
+pub struct Caption(pub String);
+
+/// Descriptions per subject, loaded from `assets/game.descriptions`,
+/// lines like `Door = A heavy door.` or `Item fish = A fresh fish.`.
+#[derive(Debug, Clone, Default, TypeUuid)]
+#[uuid = "39cadc56-aa9c-4543-8640-a018b71b5055"]
+pub struct Descriptions {
+    descriptions: HashMap<Subject, String>,
+}
+
+impl Descriptions {
+    pub fn get(&self, subject: &Subject) -> Option<&str> {
+        self.descriptions.get(subject).map(String::as_str)
+    }
+
+    /// The description, or a plain sentence naming the subject.
+    pub fn describe(&self, subject: &Subject) -> String {
+        self.get(subject)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("A {}.", subject.name()))
+    }
+}
+
+impl DataAsset for Descriptions {
+    const PATH: &'static str = DESCRIPTIONS_PATH;
+    const EXTENSIONS: &'static [&'static str] = &["descriptions"];
+
+    fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut descriptions = Self::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let subject = parts.next().unwrap_or("").trim();
+            let description = parts.next().unwrap_or("").trim();
+
+            let subject = match subject.strip_prefix("Item ") {
+                Some(item) => Some(Subject::Item(item.trim().to_string())),
+                None => Marker::parse(subject).map(Subject::Marker),
+            }
+            .ok_or_else(|| anyhow::anyhow!("line {}: unknown subject {:?}", index + 1, subject))?;
+
+            descriptions
+                .descriptions
+                .insert(subject, description.to_string());
+        }
+
+        Ok(descriptions)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub subject: Subject,
+    pub text: String,
+    /// `GameTime` seconds of the first observation.
+    pub discovered_at: f64,
+    pub times_observed: u32,
+}
+
+/// Everything observed so far, one entry per subject in order of discovery.
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Returns true when the subject was not in the journal before.
+    pub fn record(&mut self, subject: Subject, text: String, time: f64) -> bool {
+        match self.entries.iter_mut().find(|it| it.subject == subject) {
+            Some(entry) => {
+                entry.times_observed += 1;
+                false
+            }
+            None => {
+                self.entries.push(JournalEntry {
+                    subject,
+                    text,
+                    discovered_at: time,
+                    times_observed: 1,
+                });
+                true
+            }
+        }
+    }
+}
+
+struct JournalUi {
+    caption: Entity,
+    caption_timer: Timer,
+    page: Entity,
+    page_open: bool,
+}
+
+fn journal_setup(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("FiraSans-Bold.ttf");
+    let text_bundle = |font_size, position| TextBundle {
+        text: Text {
+            value: String::new(),
+            font: font.clone(),
+            style: TextStyle {
+                font_size,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        },
+        style: Style {
+            position_type: PositionType::Absolute,
+            position,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let caption = commands.entity(text_bundle(
+        28.0,
+        Rect {
+            left: Val::Percent(30.0),
+            bottom: Val::Px(48.0),
+            ..Default::default()
+        },
+    ));
+    let page = commands.entity(text_bundle(
+        20.0,
+        Rect {
+            left: Val::Px(16.0),
+            top: Val::Px(16.0),
+            ..Default::default()
+        },
+    ));
+
+    commands.insert_resource(JournalUi {
+        caption,
+        caption_timer: Timer::from_seconds(CAPTION_SECONDS, false),
+        page,
+        page_open: false,
+    });
+}
+
+fn journal_observation_system(
+    time: Res<GameTime>,
+    descriptions: Res<Descriptions>,
+    mut journal: ResMut<Journal>,
+    mut ui: ResMut<JournalUi>,
+    mut reader: Local<EventReader<ObservationEvent>>,
+    events: Res<Events<ObservationEvent>>,
+    mut text_query: Query<Mut<Text>>,
+) {
+    for event in reader.iter(&events) {
+        let text = descriptions.describe(&event.subject);
+        journal.record(event.subject.clone(), text.clone(), time.elapsed_seconds());
+        show_caption(&mut ui, &mut text_query, text);
+    }
+}
+
+fn caption_event_system(
+    mut ui: ResMut<JournalUi>,
+    mut reader: Local<EventReader<Caption>>,
+    events: Res<Events<Caption>>,
+    mut text_query: Query<Mut<Text>>,
+) {
+    for Caption(text) in reader.iter(&events) {
+        show_caption(&mut ui, &mut text_query, text.clone());
+    }
+}
+
+fn show_caption(ui: &mut JournalUi, text_query: &mut Query<Mut<Text>>, text: String) {
+    for mut caption in text_query.get_mut(ui.caption) {
+        caption.value = text.clone();
+    }
+    ui.caption_timer.reset();
+}
+
+fn journal_caption_system(
+    time: Res<GameTime>,
+    mut ui: ResMut<JournalUi>,
+    mut text_query: Query<Mut<Text>>,
+) {
+    if ui.caption_timer.finished() {
+        return;
+    }
+
+    if ui.caption_timer.tick(time.delta_seconds()).just_finished() {
+        for mut caption in text_query.get_mut(ui.caption) {
+            caption.value.clear();
+        }
+    }
+}
+
+fn journal_page_system(
+    actions: Res<ActionState>,
+    journal: Res<Journal>,
+    mut ui: ResMut<JournalUi>,
+    mut text_query: Query<Mut<Text>>,
+) {
+    if actions.just_pressed(InputAction::Journal) {
+        ui.page_open = !ui.page_open;
+    } else if !ui.page_open {
+        return;
+    }
+
+    // rebuilt while open, so edited and loaded entries show up as well
+    let value = if ui.page_open {
+        let mut lines = vec!["Journal".to_string()];
+        if journal.entries.is_empty() {
+            lines.push("Nothing observed yet.".to_string());
+        }
+        for entry in journal.entries.iter() {
+            let seconds = entry.discovered_at as u64;
+            lines.push(format!(
+                "{:02}:{:02} {}",
+                seconds / 60,
+                seconds % 60,
+                entry.text
+            ));
+        }
+        lines.join("\n")
+    } else {
+        String::new()
+    };
+
+    for mut page in text_query.get_mut(ui.page) {
+        if page.value != value {
+            page.value = value;
+        }
+    }
+}
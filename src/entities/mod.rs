@@ -1,6 +1,14 @@
+mod held_item;
+mod interaction_prompt;
 mod inventory_widget;
+mod journal;
 mod oven;
 pub mod player;
+mod world_item;
 
+pub use held_item::*;
+pub use interaction_prompt::*;
 pub use inventory_widget::*;
+pub use journal::*;
 pub use oven::*;
+pub use world_item::*;
@@ -1,6 +1,15 @@
 use bevy::prelude::*;
 
-use crate::systems::inventory::{ItemKind, Items};
+use crate::{
+    replay::GameTime,
+    systems::inventory::{ItemKind, Items},
+};
+
+/// Which way an item moves when interacting with the oven.
+pub enum OvenTransfer {
+    Put(ItemKind),
+    Take(ItemKind),
+}
 
 pub struct OvenState {
     pub baking_timer: Timer,
@@ -9,7 +18,37 @@ pub struct OvenState {
     pub on_fire: bool,
 }
 
-pub fn oven_update(time: Res<Time>, mut query: Query<Mut<OvenState>>) {
+#[test]
+fn oven_needs_a_held_fish_to_bake() {
+    use crate::systems::inventory::Item;
+    use bevy::asset::HandleId;
+
+    let items = Items {
+        fish: Handle::weak(HandleId::random::<Item>()),
+        baked_fish: Handle::weak(HandleId::random::<Item>()),
+    };
+    let mut oven = OvenState {
+        baking_timer: Timer::from_seconds(3.0, false),
+        item: None,
+        baked_item: None,
+        on_fire: false,
+    };
+
+    assert!(oven.interact(&items, Some(&items.fish)).is_none());
+    assert!(oven.on_fire);
+
+    assert!(oven.interact(&items, None).is_none());
+    assert!(oven.on_fire && oven.item.is_none());
+
+    let put = oven.interact(&items, Some(&items.fish));
+    assert!(matches!(put, Some(OvenTransfer::Put(it)) if it == items.fish));
+
+    let take = oven.interact(&items, None);
+    assert!(matches!(take, Some(OvenTransfer::Take(it)) if it == items.fish));
+    assert!(!oven.on_fire && oven.item.is_none());
+}
+
+pub fn oven_update(time: Res<GameTime>, mut query: Query<Mut<OvenState>>) {
     let delta = time.delta_seconds();
     for mut oven in query.iter_mut() {
         oven.baking_timer.tick(delta);
@@ -32,36 +71,56 @@ impl OvenState {
         }
     }
 
-    pub fn interact(&mut self, items: &Items) -> Option<ItemKind> {
+    /// What `interact` would do, as shown by the interaction prompt.
+    pub fn prompt(&self, items: &Items, held: Option<&ItemKind>) -> &'static str {
         let fire = self.on_fire;
         let baked = self.baking_timer.finished();
         let item = self.item.is_some();
+        let holds_fish = held == Some(&items.fish);
+
+        match (fire, baked, item) {
+            (false, _, false) => "Light oven",
+            (true, _, false) if holds_fish => "Bake fish",
+            (true, _, false) => "Bring a fish",
+            (_, false, true) => "Take fish",
+            (_, true, true) => "Take baked fish",
+        }
+    }
+
+    /// Lights an empty oven, bakes the held fish in a burning one and takes out
+    /// what is in it, which puts the fire out.
+    pub fn interact(&mut self, items: &Items, held: Option<&ItemKind>) -> Option<OvenTransfer> {
+        let fire = self.on_fire;
+        let baked = self.baking_timer.finished();
+        let item = self.item.is_some();
+        let holds_fish = held == Some(&items.fish);
 
         match (fire, baked, item) {
             (false, _, false) => {
                 self.on_fire = true;
                 None
             }
-            (false, false, true) => self.item.take(),
+            (false, false, true) => self.item.take().map(OvenTransfer::Take),
             (false, true, true) => {
                 self.item = None;
-                self.baked_item.take()
+                self.baked_item.take().map(OvenTransfer::Take)
             }
-            (true, _, false) => {
+            (true, _, false) if holds_fish => {
                 self.baking_timer.reset();
                 self.item = Some(items.fish.clone());
                 self.baked_item = Some(items.baked_fish.clone());
-                None
+                Some(OvenTransfer::Put(items.fish.clone()))
             }
+            (true, _, false) => None,
             (true, false, true) => {
                 self.on_fire = false;
                 self.baked_item = None;
-                self.item.take()
+                self.item.take().map(OvenTransfer::Take)
             }
             (true, true, true) => {
                 self.on_fire = false;
                 self.item = None;
-                self.baked_item.take()
+                self.baked_item.take().map(OvenTransfer::Take)
             }
         }
     }
@@ -2,9 +2,14 @@ use bevy::prelude::*;
 
 use crate::{
     bevy_rapier_utils::*,
+    collision_layer::CollisionLayer,
     commands_ext::*,
     components::*,
-    interactions::{GameInteraction, PushAway},
+    entities::{InteractionPrompt, ObservationEvent, Subject},
+    input::{ActionState, ConnectedGamepads, InputAction, InputMap, InputSource},
+    interactions::GameInteraction,
+    save::Saved,
+    systems::{camera::CameraTarget, inventory::Inventory, proximity::ProximityFilter},
 };
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -12,25 +17,53 @@ pub struct Player {
     forward_sensor: Option<Entity>,
 }
 
+impl Player {
+    pub fn forward_sensor(&self) -> Option<Entity> {
+        self.forward_sensor
+    }
+}
+
+/// Local co-op players are numbered from 0 in the order they joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PlayerId(pub u8);
+
+const PLAYER_COLORS: [Color; 4] = [Color::ORANGE, Color::CYAN, Color::LIME_GREEN, Color::PINK];
+
+impl PlayerId {
+    pub fn color(&self) -> Color {
+        PLAYER_COLORS[self.0 as usize % PLAYER_COLORS.len()]
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PlayerSpawn;
 
 #[derive(Debug, Clone, Copy)]
 pub enum PlayerEvent {
-    Observe,
-    Interact,
+    Observe(Entity),
+    Interact(Entity),
 }
 
-pub fn spawn_player(commands: &mut Commands, transform: Transform) {
+pub fn spawn_player(
+    commands: &mut Commands,
+    transform: Transform,
+    id: PlayerId,
+    source: InputSource,
+) {
     let entity = commands.entity((Marker::Player,));
     let Vec3 { x, y, .. } = transform.translation;
 
-    let mut player = Player::default();
-
     commands
         .with(transform)
         .with(GlobalTransform::default())
-        .with(Dress::Bitpack(25, Color::ORANGE))
+        .with(Dress::Bitpack(25, id.color()))
+        .with(id)
+        .with(source)
+        .with(ActionState::default())
+        .with(Inventory::default())
+        .with(Facing::default())
+        .with(CameraTarget)
+        .with(Saved)
         .with(
             RigidBodyBuilder::new_dynamic()
                 .translation(x, y)
@@ -38,27 +71,146 @@ pub fn spawn_player(commands: &mut Commands, transform: Transform) {
                 .linear_damping(8.0)
                 .lock_rotations(),
         )
-        .with_child((ColliderBuilder::ball(8.0).user_data(entity.to_user_data()),))
-        .with_a_child(|e| {
-            player.forward_sensor = Some(e);
-            (
-                ProximitySet::default(),
-                ColliderBuilder::ball(8.0)
-                    .user_data(e.to_bits() as u128)
-                    .translation(0.0, 8.0)
-                    .sensor(true),
-            )
-        })
-        .with(player);
+        .with_child((ColliderBuilder::ball(8.0)
+            .user_data(entity.to_user_data())
+            .collision_groups(CollisionLayer::Player.groups()),));
+
+    let offset = FORWARD_SENSOR_DISTANCE * Facing::default().0;
+    let sensor = commands.entity((
+        ForwardSensor { owner: entity },
+        ProximitySet::default(),
+        ProximityFilter {
+            owner: Some(entity),
+            ..Default::default()
+        },
+    ));
+    commands
+        .with(
+            RigidBodyBuilder::new_kinematic()
+                .translation(x + offset.x, y + offset.y)
+                .user_data(sensor.to_user_data()),
+        )
+        .with_child((ColliderBuilder::ball(8.0)
+            .user_data(sensor.to_user_data())
+            .collision_groups(CollisionLayer::Sensor.groups())
+            .sensor(true),));
+
+    commands.insert_one(
+        entity,
+        Player {
+            forward_sensor: Some(sensor),
+        },
+    );
+}
+
+const FORWARD_SENSOR_DISTANCE: f32 = 8.0;
+
+/// The sensor in front of a player, it lives on its own body and leaves with its player.
+#[derive(Debug, Clone, Copy)]
+pub struct ForwardSensor {
+    pub owner: Entity,
+}
+
+pub fn player_facing_system(mut query: Query<(&ActionState, Mut<Facing>), With<Player>>) {
+    for (actions, mut facing) in query.iter_mut() {
+        for new_facing in Facing::from_movement(actions.movement()) {
+            if *facing != new_facing {
+                *facing = new_facing;
+            }
+        }
+    }
+}
+
+pub fn player_facing_flip_system(
+    mut query: Query<(&Facing, Mut<Transform>), (With<Player>, Changed<Facing>)>,
+) {
+    for (facing, mut trans) in query.iter_mut() {
+        for flip_x in facing.flip_x() {
+            let scale_x = if flip_x { -1.0 } else { 1.0 } * trans.scale.x.abs();
+            if trans.scale.x != scale_x {
+                trans.scale.x = scale_x;
+            }
+        }
+    }
 }
 
+/// Colliders can not be moved relative to their body, so the sensor has a kinematic body
+/// that is moved in front of its player. It keeps its `ProximitySet` while turning.
+pub fn player_forward_sensor_system(
+    commands: &mut Commands,
+    mut bodies: ResMut<RigidBodySet>,
+    sensors: Query<(Entity, &ForwardSensor, &RigidBodyHandleComponent)>,
+    players: Query<(&Facing, Option<&RigidBodyHandleComponent>)>,
+) {
+    for (sensor, forward_sensor, sensor_body) in sensors.iter() {
+        let (facing, player_body) = match players.get(forward_sensor.owner) {
+            Ok(it) => it,
+            Err(_) => {
+                commands.despawn_recursive(sensor);
+                continue;
+            }
+        };
+        let translation = match player_body.and_then(|it| bodies.get(it.handle())) {
+            Some(body) => body.position().translation,
+            None => continue,
+        };
+
+        let offset = FORWARD_SENSOR_DISTANCE * facing.0;
+        for body in bodies.get_mut(sensor_body.handle()) {
+            body.set_next_kinematic_position(Isometry::translation(
+                translation.x + offset.x,
+                translation.y + offset.y,
+            ));
+        }
+    }
+}
+
+/// Spawns the first player on the first spawn point and despawns the spawn points.
 pub fn player_spawn_system(
     commands: &mut Commands,
-    query: Query<(Entity, &PlayerSpawn, &Transform)>,
+    query: Query<(Entity, &Transform), Added<PlayerSpawn>>,
+    players: Query<&PlayerId>,
 ) {
-    for (entity, _spawn, trans) in query.iter() {
-        spawn_player(commands, trans.clone());
-        commands.despawn(entity);
+    let mut first = players.iter().next().is_none();
+    for (entity, trans) in query.iter() {
+        if first {
+            spawn_player(commands, trans.clone(), PlayerId(0), InputSource::Any);
+            first = false;
+        }
+        commands.despawn_recursive(entity);
+    }
+}
+
+/// Joining players start this far right of the first player, one step per player id.
+const JOIN_OFFSET: f32 = 16.0;
+
+/// A gamepad that is not claimed by a player joins the game with the `Join` action.
+pub fn player_join_system(
+    commands: &mut Commands,
+    map: Res<InputMap>,
+    gamepads: Res<ConnectedGamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    players: Query<(&PlayerId, &InputSource, &Transform)>,
+) {
+    let mut next_id = players.iter().map(|(id, _, _)| id.0 + 1).max().unwrap_or(0);
+    let first = players
+        .iter()
+        .min_by_key(|(id, _, _)| **id)
+        .map(|(_, _, trans)| trans.clone());
+
+    for gamepad in gamepads.0.iter() {
+        let claimed = players
+            .iter()
+            .any(|(_, source, _)| *source == InputSource::Gamepad(*gamepad));
+
+        if !claimed && map.gamepad_just_pressed(InputAction::Join, *gamepad, &buttons) {
+            for mut trans in first.clone() {
+                trans.translation.x += JOIN_OFFSET * next_id as f32;
+                let source = InputSource::Gamepad(*gamepad);
+                spawn_player(commands, trans, PlayerId(next_id), source);
+                next_id += 1;
+            }
+        }
     }
 }
 
@@ -68,58 +220,56 @@ pub fn player_handle_input_events(
     mut reader: Local<EventReader<PlayerEvent>>,
     events: Res<Events<PlayerEvent>>,
     mut interactions: ResMut<Events<GameInteraction>>,
+    mut observations: ResMut<Events<ObservationEvent>>,
     //
-    query: Query<(Entity, &Player)>,
+    query: Query<&Player>,
+    prompts: Query<&InteractionPrompt>,
     proximity: Query<&ProximitySet>,
     observe: Query<&Marker>,
 ) {
     for ev in reader.iter(&events) {
-        match ev {
-            PlayerEvent::Interact => {
-                for (entity, player) in query.iter() {
-                    player_interact(entity, player, &proximity, &mut interactions);
+        match *ev {
+            PlayerEvent::Interact(entity) => {
+                for prompt in prompts.get(entity) {
+                    player_interact(entity, prompt, &mut interactions);
                 }
             }
-            PlayerEvent::Observe => {
-                for (entity, player) in query.iter() {
-                    player_observe(entity, player, &proximity, &observe);
+            PlayerEvent::Observe(entity) => {
+                for player in query.get(entity) {
+                    player_observe(entity, player, &proximity, &observe, &mut observations);
                 }
             }
         }
     }
 }
 
+/// Interacts with the target of the prompt only, so the prompt shows what will happen.
 fn player_interact(
     entity: Entity,
-    player: &Player,
-    proximity: &Query<&ProximitySet>,
+    prompt: &InteractionPrompt,
     interactions: &mut Events<GameInteraction>,
 ) {
-    for proximity in player.forward_sensor.and_then(|it| proximity.get(it).ok()) {
-        for near_entity in proximity.iter() {
-            interactions.send(GameInteraction::PushAway(PushAway {
-                which: *near_entity,
-                relative_to: entity,
-                rel_impulse: 20.0,
-            }));
-        }
+    for which in prompt.target {
+        interactions.send(GameInteraction::Interact {
+            actor: entity,
+            which,
+        });
     }
 }
 
 fn player_observe(
-    _entity: Entity,
+    entity: Entity,
     player: &Player,
     proximity: &Query<&ProximitySet>,
     observe: &Query<&Marker>,
+    observations: &mut Events<ObservationEvent>,
 ) {
     for proximity in player.forward_sensor.and_then(|it| proximity.get(it).ok()) {
-        let observations = proximity
-            .iter()
-            .filter_map(|e| observe.get(*e).ok())
-            .collect::<Vec<_>>();
-
-        if !observations.is_empty() {
-            println!("{:?}", observations);
+        for marker in proximity.iter().filter_map(|e| observe.get(*e).ok()) {
+            observations.send(ObservationEvent {
+                observer: entity,
+                subject: Subject::Marker(*marker),
+            });
         }
     }
 }
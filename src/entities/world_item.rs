@@ -0,0 +1,199 @@
+use bevy::{prelude::*, utils::HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bevy_rapier_utils::*,
+    collision_layer::CollisionLayer,
+    commands_ext::CommandsExt,
+    entities::InventoryWidget,
+    replay::GameTime,
+    systems::inventory::{Inventory, Item, ItemKind, Items},
+};
+
+/// An item lying in the world, outside of any inventory.
+pub struct WorldItem(pub ItemKind);
+
+/// Freshly dropped items can not be picked up by a magnet until the timer finished.
+pub struct PickupCooldown(pub Timer);
+
+/// Picks up world items within `radius` into `inventory` without any interaction,
+/// as long as the inventory has room.
+pub struct ItemMagnet {
+    pub radius: f32,
+    pub inventory: Entity,
+}
+
+pub enum WorldItemEvent {
+    Drop {
+        item: ItemKind,
+        from: Entity,
+        position: Vec3,
+        velocity: Vec2,
+    },
+    PickUp {
+        item: Entity,
+        inventory: Entity,
+    },
+}
+
+/// Position of a world item as it is written into a save game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldItemRecord {
+    pub item: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+pub fn spawn_world_item(
+    commands: &mut Commands,
+    kind: ItemKind,
+    item: &Item,
+    position: Vec3,
+    velocity: Vec2,
+) -> Entity {
+    let entity = commands.entity((
+        WorldItem(kind),
+        PickupCooldown(Timer::from_seconds(1.0, false)),
+        Transform::from_translation(position),
+        GlobalTransform::default(),
+    ));
+
+    commands
+        .with(
+            RigidBodyBuilder::new_dynamic()
+                .translation(position.x, position.y)
+                .linvel(velocity.x, velocity.y)
+                .linear_damping(6.0)
+                .lock_rotations()
+                .user_data(entity.to_user_data()),
+        )
+        .with_child((ColliderBuilder::ball(2.0)
+            .user_data(entity.to_user_data())
+            .collision_groups(CollisionLayer::Item.groups()),))
+        .with_child(item.sprite_sheet_bundle());
+
+    entity
+}
+
+pub fn world_item_events_system(
+    commands: &mut Commands,
+    mut reader: Local<EventReader<WorldItemEvent>>,
+    events: Res<Events<WorldItemEvent>>,
+    items: Res<Assets<Item>>,
+    world_item_query: Query<&WorldItem>,
+    mut inventory_query: Query<(Mut<Inventory>, Option<&InventoryWidget>)>,
+) {
+    let mut picked_up = HashSet::default();
+
+    for event in reader.iter(&events) {
+        match event {
+            WorldItemEvent::Drop {
+                item,
+                from,
+                position,
+                velocity,
+            } => {
+                for (mut inventory, _) in inventory_query.get_mut(*from) {
+                    if inventory.has(item) {
+                        inventory.take(item);
+
+                        for asset in items.get(item) {
+                            spawn_world_item(commands, item.clone(), asset, *position, *velocity);
+                        }
+                    }
+                }
+            }
+            WorldItemEvent::PickUp { item, inventory } => {
+                if !picked_up.insert(*item) {
+                    continue;
+                }
+
+                if let Ok(WorldItem(kind)) = world_item_query.get(*item) {
+                    for (mut inventory, widget) in inventory_query.get_mut(*inventory) {
+                        if has_room(&inventory, widget) {
+                            inventory.put(kind);
+                            commands.despawn_recursive(*item);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn pickup_cooldown_system(
+    commands: &mut Commands,
+    time: Res<GameTime>,
+    mut query: Query<(Entity, Mut<PickupCooldown>)>,
+) {
+    for (entity, mut cooldown) in query.iter_mut() {
+        if cooldown.0.tick(time.delta_seconds()).finished() {
+            commands.remove_one::<PickupCooldown>(entity);
+        }
+    }
+}
+
+pub fn item_magnet_system(
+    mut events: ResMut<Events<WorldItemEvent>>,
+    magnet_query: Query<(&ItemMagnet, &GlobalTransform)>,
+    item_query: Query<(Entity, &GlobalTransform), (With<WorldItem>, Without<PickupCooldown>)>,
+    inventory_query: Query<(&Inventory, Option<&InventoryWidget>)>,
+) {
+    for (magnet, magnet_trans) in magnet_query.iter() {
+        let full = inventory_query
+            .get(magnet.inventory)
+            .map_or(true, |(inventory, widget)| !has_room(inventory, widget));
+        if full {
+            continue;
+        }
+
+        let magnet_pos = magnet_trans.translation.truncate();
+
+        for (item, item_trans) in item_query.iter() {
+            if item_trans.translation.truncate().distance(magnet_pos) < magnet.radius {
+                events.send(WorldItemEvent::PickUp {
+                    item,
+                    inventory: magnet.inventory,
+                });
+            }
+        }
+    }
+}
+
+/// Inventories shown in a widget hold as many items as it has slots, others have no limit.
+fn has_room(inventory: &Inventory, widget: Option<&InventoryWidget>) -> bool {
+    widget.map_or(true, |widget| inventory.items.len() < widget.slots.len())
+}
+
+pub fn world_item_records(
+    items: &Assets<Item>,
+    query: &Query<(&WorldItem, &Transform)>,
+) -> Vec<WorldItemRecord> {
+    query
+        .iter()
+        .filter_map(|(WorldItem(kind), trans)| {
+            Some(WorldItemRecord {
+                item: items.get(kind)?.name.to_string(),
+                x: trans.translation.x,
+                y: trans.translation.y,
+            })
+        })
+        .collect()
+}
+
+pub fn spawn_world_item_records(
+    commands: &mut Commands,
+    records: &[WorldItemRecord],
+    z: f32,
+    items: &Items,
+    assets: &Assets<Item>,
+) {
+    for record in records {
+        for kind in items.by_name(assets, &record.item) {
+            for item in assets.get(&kind) {
+                let position = Vec3::new(record.x, record.y, z);
+                spawn_world_item(commands, kind.clone(), item, position, Vec2::zero());
+            }
+        }
+    }
+}
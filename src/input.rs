@@ -1,108 +1,723 @@
-use crate::stuff;
-
-use bevy::{
-    input::{
-        keyboard::KeyboardInput,
-        mouse::{MouseButtonInput, MouseMotion, MouseWheel},
-    },
-    prelude::*
-};
-
-#[derive(Default)]
-pub struct MyInputState {
-    keys: EventReader<KeyboardInput>,
-    cursor: EventReader<CursorMoved>,
-    motion: EventReader<MouseMotion>,
-    mousebtn: EventReader<MouseButtonInput>,
-    scroll: EventReader<MouseWheel>,
-}
-
-macro_rules! debug {
-    ($($arg:tt)*) => ({
-        if false { eprintln!($($arg)*); }
-    })
-}
-
-pub fn my_input_system(
-    mut state: ResMut<MyInputState>,
-    ev_keys: Res<Events<KeyboardInput>>,
-    ev_cursor: Res<Events<CursorMoved>>,
-    ev_motion: Res<Events<MouseMotion>>,
-    ev_mousebtn: Res<Events<MouseButtonInput>>,
-    ev_scroll: Res<Events<MouseWheel>>,
-) {
-    // Keyboard input
-    for ev in state.keys.iter(&ev_keys) {
-        if ev.state.is_pressed() {
-            debug!("Just pressed key: {:?}", ev.key_code);
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashSet};
+
+use crate::data_asset::{DataAsset, DataAssetPlugin};
+
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(DataAssetPlugin::<InputMap>::default())
+            .init_resource::<ActionState>()
+            .init_resource::<ConnectedGamepads>()
+            .init_resource::<InputCapture>()
+            .add_system_to_stage(stage::EVENT, gamepad_connection_system.system())
+            .add_system_to_stage(stage::PRE_UPDATE, action_state_system.system())
+            .add_system_to_stage(stage::PRE_UPDATE, source_action_state_system.system())
+            .add_system_to_stage(stage::PRE_UPDATE, input_capture_system.system());
+    }
+}
+
+#[test]
+fn parse_keymap() {
+    let map = InputMap::parse(
+        "# comment\n\
+         MoveUp = W, Up\n\
+         Interact = E, GamepadSouth\n\
+         SelectSlot3 = Key3\n\
+         Undo = LControl+Z\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        map.bindings(InputAction::MoveUp).collect::<Vec<_>>(),
+        vec![&Binding::Key(KeyCode::W), &Binding::Key(KeyCode::Up)]
+    );
+    assert_eq!(
+        map.bindings(InputAction::Interact).collect::<Vec<_>>(),
+        vec![
+            &Binding::Key(KeyCode::E),
+            &Binding::Gamepad(GamepadButtonType::South)
+        ]
+    );
+    assert_eq!(
+        map.bindings(InputAction::SelectSlot(2)).collect::<Vec<_>>(),
+        vec![&Binding::Key(KeyCode::Key3)]
+    );
+    assert_eq!(
+        map.bindings(InputAction::Undo).collect::<Vec<_>>(),
+        vec![&Binding::Chord(KeyCode::LControl, KeyCode::Z)]
+    );
+    assert!(InputMap::parse("Jump = W").is_err());
+    assert_eq!(InputMap::parse("Deadzone = 0.3").unwrap().deadzone, 0.3);
+}
+
+#[test]
+fn stick_deadzone() {
+    assert_eq!(apply_deadzone(Vec2::new(0.1, 0.1), 0.2), Vec2::zero());
+    assert_eq!(
+        apply_deadzone(Vec2::new(0.0, 1.0), 0.2),
+        Vec2::new(0.0, 1.0)
+    );
+    assert!((apply_deadzone(Vec2::new(0.6, 0.0), 0.2).x - 0.5).abs() < 1e-6);
+}
+
+pub const INPUT_MAP_PATH: &'static str = "input.keymap";
+
+/// Logical actions the game reacts to, independent of the device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum InputAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    Observe,
+    Drop,
+    Throw,
+    /// Zero based, but written one based in the keymap like the number keys.
+    SelectSlot(u8),
+    ClearSlot,
+    NextSlot,
+    PrevSlot,
+    Journal,
+    QuickSave,
+    QuickLoad,
+    Menu,
+    Join,
+    ToggleEditor,
+    EditorNextLayer,
+    EditorPrevTile,
+    EditorNextTile,
+    Undo,
+    Redo,
+    SaveTileMap,
+    ToggleRapierDebug,
+    ToggleFreeCamera,
+    FreeCameraUp,
+    FreeCameraDown,
+    FreeCameraLeft,
+    FreeCameraRight,
+}
+
+impl InputAction {
+    pub fn parse(name: &str) -> Option<Self> {
+        use InputAction::*;
+        let action = match name {
+            "MoveUp" => MoveUp,
+            "MoveDown" => MoveDown,
+            "MoveLeft" => MoveLeft,
+            "MoveRight" => MoveRight,
+            "Interact" => Interact,
+            "Observe" => Observe,
+            "Drop" => Drop,
+            "Throw" => Throw,
+            "ClearSlot" => ClearSlot,
+            "NextSlot" => NextSlot,
+            "PrevSlot" => PrevSlot,
+            "Journal" => Journal,
+            "QuickSave" => QuickSave,
+            "QuickLoad" => QuickLoad,
+            "Menu" => Menu,
+            "Join" => Join,
+            "ToggleEditor" => ToggleEditor,
+            "EditorNextLayer" => EditorNextLayer,
+            "EditorPrevTile" => EditorPrevTile,
+            "EditorNextTile" => EditorNextTile,
+            "Undo" => Undo,
+            "Redo" => Redo,
+            "SaveTileMap" => SaveTileMap,
+            "ToggleRapierDebug" => ToggleRapierDebug,
+            "ToggleFreeCamera" => ToggleFreeCamera,
+            "FreeCameraUp" => FreeCameraUp,
+            "FreeCameraDown" => FreeCameraDown,
+            "FreeCameraLeft" => FreeCameraLeft,
+            "FreeCameraRight" => FreeCameraRight,
+            _ => {
+                let slot = name.strip_prefix("SelectSlot")?.parse::<u8>().ok()?;
+                SelectSlot(slot.checked_sub(1)?)
+            }
+        };
+        Some(action)
+    }
+
+    /// The name used in the keymap, the inverse of `parse`.
+    pub fn name(&self) -> String {
+        match self {
+            InputAction::SelectSlot(slot) => format!("SelectSlot{}", *slot as u16 + 1),
+            action => format!("{:?}", action),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    /// A modifier held together with a key, written `LControl+S`.
+    Chord(KeyCode, KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some(button) = name.strip_prefix("Gamepad") {
+            parse_gamepad_button(button).map(Binding::Gamepad)
+        } else if let Some(button) = name.strip_prefix("Mouse") {
+            parse_mouse_button(button).map(Binding::Mouse)
+        } else if name.contains('+') {
+            let mut parts = name.splitn(2, '+');
+            let modifier = parse_key(parts.next()?)?;
+            let key = parse_key(parts.next()?)?;
+            Some(Binding::Chord(modifier, key))
         } else {
-            debug!("Just released key: {:?}", ev.key_code);
+            parse_key(name).map(Binding::Key)
         }
     }
 
-    // Absolute cursor position (in window coordinates)
-    for ev in state.cursor.iter(&ev_cursor) {
-        debug!("Cursor at: {}", ev.position);
+    /// Short name to show in prompts.
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Chord(modifier, key) => format!("{:?}+{:?}", modifier, key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Gamepad(button) => format!("{:?}", button),
+        }
     }
+}
+
+/// Maps logical actions to keys, mouse buttons and gamepad buttons.
+/// Loaded from `assets/input.keymap`, lines like `Interact = E, GamepadSouth`.
+/// Movement additionally reads the left stick of every connected gamepad.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "39cadc56-aa9c-4543-8640-a018b71b5053"]
+pub struct InputMap {
+    bindings: Vec<(InputAction, Binding)>,
+    /// Stick values below this length are ignored, written as `Deadzone = 0.2`.
+    pub deadzone: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use InputAction::*;
+        use KeyCode::*;
+
+        let mut map = Self {
+            bindings: vec![],
+            deadzone: DEFAULT_DEADZONE,
+        };
+        map.bind(MoveUp, Binding::Key(W))
+            .bind(MoveDown, Binding::Key(S))
+            .bind(MoveLeft, Binding::Key(A))
+            .bind(MoveRight, Binding::Key(D))
+            .bind(Interact, Binding::Key(E))
+            .bind(Observe, Binding::Key(F))
+            .bind(Drop, Binding::Key(Q))
+            .bind(Throw, Binding::Key(T))
+            .bind(Journal, Binding::Key(J))
+            .bind(ClearSlot, Binding::Key(Key0))
+            .bind(QuickSave, Binding::Key(F5))
+            .bind(QuickLoad, Binding::Key(F9))
+            .bind(Menu, Binding::Key(Escape))
+            .bind(ToggleEditor, Binding::Key(F2))
+            .bind(EditorNextLayer, Binding::Key(Tab))
+            .bind(EditorPrevTile, Binding::Key(LBracket))
+            .bind(EditorNextTile, Binding::Key(RBracket))
+            .bind(ToggleRapierDebug, Binding::Key(F3))
+            .bind(ToggleFreeCamera, Binding::Key(F4))
+            .bind(FreeCameraUp, Binding::Key(Up))
+            .bind(FreeCameraDown, Binding::Key(Down))
+            .bind(FreeCameraLeft, Binding::Key(Left))
+            .bind(FreeCameraRight, Binding::Key(Right))
+            .bind(Interact, Binding::Gamepad(GamepadButtonType::South))
+            .bind(Observe, Binding::Gamepad(GamepadButtonType::West))
+            .bind(NextSlot, Binding::Gamepad(GamepadButtonType::RightTrigger))
+            .bind(PrevSlot, Binding::Gamepad(GamepadButtonType::LeftTrigger))
+            .bind(Journal, Binding::Gamepad(GamepadButtonType::Select))
+            .bind(Menu, Binding::Gamepad(GamepadButtonType::Mode))
+            .bind(Join, Binding::Gamepad(GamepadButtonType::Start));
 
-    // Relative mouse motion
-    for ev in state.motion.iter(&ev_motion) {
-        debug!("Mouse moved {} pixels", ev.delta);
+        for modifier in [LControl, RControl].iter() {
+            map.bind(Undo, Binding::Chord(*modifier, Z))
+                .bind(Redo, Binding::Chord(*modifier, Y))
+                .bind(SaveTileMap, Binding::Chord(*modifier, S));
+        }
+
+        for (slot, key) in [Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8]
+            .iter()
+            .enumerate()
+        {
+            map.bind(SelectSlot(slot as u8), Binding::Key(*key));
+        }
+        map
+    }
+}
+
+impl InputMap {
+    pub fn bind(&mut self, action: InputAction, binding: Binding) -> &mut Self {
+        self.bindings.push((action, binding));
+        self
+    }
+
+    pub fn bindings(&self, action: InputAction) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(move |(a, _)| *a == action)
+            .map(|(_, b)| b)
+    }
+
+    /// Whether one of the gamepad buttons of `action` was just pressed on `gamepad`,
+    /// for gamepads that do not drive an `ActionState` of their own yet.
+    pub fn gamepad_just_pressed(
+        &self,
+        action: InputAction,
+        gamepad: Gamepad,
+        buttons: &Input<GamepadButton>,
+    ) -> bool {
+        self.bindings(action).any(|binding| match binding {
+            Binding::Gamepad(button) => buttons.just_pressed(GamepadButton(gamepad, *button)),
+            _ => false,
+        })
+    }
+
+    /// Name of the first binding of `action` the devices of `source` can press.
+    pub fn binding_name(&self, action: InputAction, source: InputSource) -> Option<String> {
+        self.bindings(action)
+            .find(|binding| match (source, binding) {
+                (InputSource::Any, _) => true,
+                (InputSource::Keyboard, Binding::Gamepad(_)) => false,
+                (InputSource::Keyboard, _) => true,
+                (InputSource::Gamepad(_), binding) => matches!(binding, Binding::Gamepad(_)),
+            })
+            .map(|binding| binding.name())
+    }
+}
+
+impl DataAsset for InputMap {
+    const PATH: &'static str = INPUT_MAP_PATH;
+    const EXTENSIONS: &'static [&'static str] = &["keymap"];
+
+    fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut map = Self {
+            bindings: vec![],
+            deadzone: DEFAULT_DEADZONE,
+        };
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let action = parts.next().unwrap_or("").trim();
+            let bindings = parts.next().unwrap_or("");
+
+            if action == "Deadzone" {
+                map.deadzone = bindings.trim().parse()?;
+                continue;
+            }
+
+            let action = InputAction::parse(action).ok_or_else(|| {
+                anyhow::anyhow!("line {}: unknown action {:?}", index + 1, action)
+            })?;
+
+            for binding in bindings.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                let binding = Binding::parse(binding).ok_or_else(|| {
+                    anyhow::anyhow!("line {}: unknown binding {:?}", index + 1, binding)
+                })?;
+                map.bind(action, binding);
+            }
+        }
+
+        Ok(map)
     }
+}
+
+const DEFAULT_DEADZONE: f32 = 0.2;
+
+/// Radial deadzone, the remaining range is rescaled to start at 0.0 again.
+pub fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= deadzone {
+        Vec2::zero()
+    } else {
+        stick / length * ((length - deadzone) / (1.0 - deadzone)).min(1.0)
+    }
+}
+
+/// Lets a tool like the tilemap editor take over the input. While `active`, every
+/// `ActionState` only keeps the actions in `keep` and the pointer belongs to the tool.
+#[derive(Debug, Default)]
+pub struct InputCapture {
+    pub active: bool,
+    pub keep: Vec<InputAction>,
+}
 
-    // Mouse buttons
-    for ev in state.mousebtn.iter(&ev_mousebtn) {
-        if ev.state.is_pressed() {
-            debug!("Just pressed mouse button: {:?}", ev.button);
+/// Connected gamepads in the order they were connected.
+#[derive(Default, Debug)]
+pub struct ConnectedGamepads(pub Vec<Gamepad>);
+
+/// State of all logical actions for the current frame.
+#[derive(Default, Debug, Clone)]
+pub struct ActionState {
+    pressed: HashSet<InputAction>,
+    just_pressed: HashSet<InputAction>,
+    just_released: HashSet<InputAction>,
+    stick: Vec2,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: InputAction) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    pub fn pressed_actions(&self) -> impl Iterator<Item = &InputAction> {
+        self.pressed.iter()
+    }
+
+    /// -1.0, 0.0 or 1.0 depending on which of the two actions is pressed.
+    pub fn axis(&self, negative: InputAction, positive: InputAction) -> f32 {
+        let value = |action| if self.pressed(action) { 1.0 } else { 0.0 };
+        value(positive) - value(negative)
+    }
+
+    /// Analog stick after the deadzone, zero without a gamepad.
+    pub fn stick(&self) -> Vec2 {
+        self.stick
+    }
+
+    /// Movement direction with a length of at most 1.0.
+    /// The analog stick wins over digital movement when it is pushed.
+    pub fn movement(&self) -> Vec2 {
+        use InputAction::*;
+        let movement = if self.stick != Vec2::zero() {
+            self.stick
         } else {
-            debug!("Just released mouse button: {:?}", ev.button);
+            Vec2::new(self.axis(MoveLeft, MoveRight), self.axis(MoveDown, MoveUp))
+        };
+
+        if movement.length_squared() > 1.0 {
+            movement.normalize()
+        } else {
+            movement
+        }
+    }
+
+    /// The last slot selection made this frame, `Some(None)` clears the selection.
+    pub fn slot_selection(&self) -> Option<Option<usize>> {
+        if self.just_pressed(InputAction::ClearSlot) {
+            return Some(None);
         }
+
+        self.just_pressed
+            .iter()
+            .filter_map(|action| match action {
+                InputAction::SelectSlot(slot) => Some(*slot as usize),
+                _ => None,
+            })
+            .max()
+            .map(Some)
+    }
+
+    /// -1, 0 or 1 when the next or previous slot is requested this frame.
+    pub fn slot_cycle(&self) -> isize {
+        let value = |action| if self.just_pressed(action) { 1 } else { 0 };
+        value(InputAction::NextSlot) - value(InputAction::PrevSlot)
     }
 
-    // scrolling (mouse wheel, touchpad, etc.)
-    for ev in state.scroll.iter(&ev_scroll) {
-        debug!(
-            "Scrolled vertically by {} and horizontally by {}.",
-            ev.y, ev.x
-        );
+    pub fn update(&mut self, pressed: HashSet<InputAction>, stick: Vec2) {
+        self.just_pressed = pressed.difference(&self.pressed).cloned().collect();
+        self.just_released = self.pressed.difference(&pressed).cloned().collect();
+        self.pressed = pressed;
+        self.stick = stick;
+    }
+
+    /// Releases every action but the ones in `keep`, without a `just_released`.
+    pub fn release_except(&mut self, keep: &[InputAction]) {
+        self.pressed.retain(|it| keep.contains(it));
+        self.just_pressed.retain(|it| keep.contains(it));
+        self.just_released.retain(|it| keep.contains(it));
+        self.stick = Vec2::zero();
     }
 }
 
-#[test]
-fn xsystem() {
-    App::build().add_system(system);
+pub fn gamepad_connection_system(
+    mut gamepads: ResMut<ConnectedGamepads>,
+    mut event_reader: Local<EventReader<GamepadEvent>>,
+    events: Res<Events<GamepadEvent>>,
+) {
+    for GamepadEvent(gamepad, event) in event_reader.iter(&events) {
+        match event {
+            GamepadEventType::Connected => {
+                if !gamepads.0.contains(gamepad) {
+                    gamepads.0.push(*gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                gamepads.0.retain(|it| it != gamepad);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Which devices drive a player, `Any` takes the keyboard and all gamepads
+/// that are not claimed by another player.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum InputSource {
+    Any,
+    Keyboard,
+    Gamepad(Gamepad),
+}
+
+struct InputDevices<'a> {
+    keys: &'a Input<KeyCode>,
+    mouse: &'a Input<MouseButton>,
+    gamepad_buttons: &'a Input<GamepadButton>,
+    gamepad_axes: &'a Axis<GamepadAxis>,
+}
+
+impl InputDevices<'_> {
+    fn read(
+        &self,
+        map: &InputMap,
+        keyboard: bool,
+        gamepads: &[Gamepad],
+    ) -> (HashSet<InputAction>, Vec2) {
+        let pressed = map
+            .bindings
+            .iter()
+            .filter(|(_, binding)| match binding {
+                Binding::Key(key) => keyboard && self.keys.pressed(*key),
+                Binding::Chord(modifier, key) => {
+                    keyboard && self.keys.pressed(*modifier) && self.keys.pressed(*key)
+                }
+                Binding::Mouse(button) => keyboard && self.mouse.pressed(*button),
+                Binding::Gamepad(button) => gamepads.iter().any(|gamepad| {
+                    self.gamepad_buttons
+                        .pressed(GamepadButton(*gamepad, *button))
+                }),
+            })
+            .map(|(action, _)| *action)
+            .collect();
+
+        let mut stick = Vec2::zero();
+        for gamepad in gamepads.iter() {
+            let axis = |axis_type| {
+                self.gamepad_axes
+                    .get(GamepadAxis(*gamepad, axis_type))
+                    .unwrap_or(0.0)
+            };
+            let raw = Vec2::new(
+                axis(GamepadAxisType::LeftStickX),
+                axis(GamepadAxisType::LeftStickY),
+            );
+            stick += apply_deadzone(raw, map.deadzone);
+        }
+
+        if stick.length_squared() > 1.0 {
+            stick = stick.normalize();
+        }
+
+        (pressed, stick)
+    }
+}
+
+/// Merges all devices into the `ActionState` resource.
+pub fn action_state_system(
+    map: Res<InputMap>,
+    gamepads: Res<ConnectedGamepads>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut state: ResMut<ActionState>,
+) {
+    let devices = InputDevices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+    };
+
+    let (pressed, stick) = devices.read(&map, true, &gamepads.0);
+    state.update(pressed, stick);
 }
 
-pub fn system(
-    game_state: Res<stuff::GameState>,
-    time: Res<Time>,
-    (keys, btns, windows): (Res<Input<KeyCode>>, Res<Input<MouseButton>>, Res<Windows>),
-    mut transforms: Query<&mut Transform>,
+/// Runs after the action states were read, so captured actions never reach gameplay.
+pub fn input_capture_system(
+    capture: Res<InputCapture>,
+    mut global: ResMut<ActionState>,
+    mut query: Query<Mut<ActionState>>,
 ) {
-    let pressed = |code| if keys.pressed(code) { 1.0 } else { 0.0 };
-    let x = pressed(KeyCode::D) - pressed(KeyCode::A);
-    let y = pressed(KeyCode::W) - pressed(KeyCode::S);
-    let s = pressed(KeyCode::Plus) - pressed(KeyCode::Minus);
-    let r = pressed(KeyCode::E) - pressed(KeyCode::Q);
-    if x != 0.0 || y != 0.0 || s != 0.0 || r != 0.0 {
-        let vec = Vec3::new(x, y, 0.0);
-        let scale_power = 1.1_f32.powf(-s);
-        let scale = Vec3::new(scale_power, scale_power, 1.0);
-        let trans = 40.0 * vec * time.delta_seconds();
-        let angle = 3.14159 * 0.1 * r * time.delta_seconds();
-
-        let mut transform = transforms
-            .get_mut(game_state.camera)
-            .expect("camera transform");
-        transform.scale *= scale;
-        transform.translation += trans * scale;
-        transform.rotate(Quat::from_rotation_z(angle));
-    }
-
-    if btns.just_pressed(MouseButton::Left) {}
-
-    if let Some(window) = windows.get_primary() {
-        let pos = window.cursor_position();
-        debug!("XXX {:?}", pos);
+    if !capture.active {
+        return;
     }
+
+    global.release_except(&capture.keep);
+    for mut state in query.iter_mut() {
+        state.release_except(&capture.keep);
+    }
+}
+
+/// Updates the `ActionState` component of every entity from its own `InputSource`.
+pub fn source_action_state_system(
+    map: Res<InputMap>,
+    gamepads: Res<ConnectedGamepads>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut query: Query<(&InputSource, Mut<ActionState>)>,
+) {
+    let devices = InputDevices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+    };
+
+    let claimed: Vec<Gamepad> = query
+        .iter_mut()
+        .filter_map(|(source, _)| match source {
+            InputSource::Gamepad(gamepad) => Some(*gamepad),
+            _ => None,
+        })
+        .collect();
+
+    for (source, mut state) in query.iter_mut() {
+        let (pressed, stick) = match source {
+            InputSource::Any => {
+                let unclaimed: Vec<Gamepad> = gamepads
+                    .0
+                    .iter()
+                    .filter(|it| !claimed.contains(*it))
+                    .cloned()
+                    .collect();
+                devices.read(&map, true, &unclaimed)
+            }
+            InputSource::Keyboard => devices.read(&map, true, &[]),
+            InputSource::Gamepad(gamepad) => devices.read(&map, false, &[*gamepad]),
+        };
+        state.update(pressed, stick);
+    }
+}
+
+fn parse_mouse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "Left" => Some(MouseButton::Left),
+        "Right" => Some(MouseButton::Right),
+        "Middle" => Some(MouseButton::Middle),
+        _ => None,
+    }
+}
+
+fn parse_gamepad_button(name: &str) -> Option<GamepadButtonType> {
+    use GamepadButtonType::*;
+    let button = match name {
+        "South" => South,
+        "East" => East,
+        "North" => North,
+        "West" => West,
+        "C" => C,
+        "Z" => Z,
+        "LeftTrigger" => LeftTrigger,
+        "LeftTrigger2" => LeftTrigger2,
+        "RightTrigger" => RightTrigger,
+        "RightTrigger2" => RightTrigger2,
+        "Select" => Select,
+        "Start" => Start,
+        "Mode" => Mode,
+        "LeftThumb" => LeftThumb,
+        "RightThumb" => RightThumb,
+        "DPadUp" => DPadUp,
+        "DPadDown" => DPadDown,
+        "DPadLeft" => DPadLeft,
+        "DPadRight" => DPadRight,
+        _ => return None,
+    };
+    Some(button)
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    use KeyCode::*;
+    let key = match name {
+        "Key1" => Key1,
+        "Key2" => Key2,
+        "Key3" => Key3,
+        "Key4" => Key4,
+        "Key5" => Key5,
+        "Key6" => Key6,
+        "Key7" => Key7,
+        "Key8" => Key8,
+        "Key9" => Key9,
+        "Key0" => Key0,
+        "A" => A,
+        "B" => B,
+        "C" => C,
+        "D" => D,
+        "E" => E,
+        "F" => F,
+        "G" => G,
+        "H" => H,
+        "I" => I,
+        "J" => J,
+        "K" => K,
+        "L" => L,
+        "M" => M,
+        "N" => N,
+        "O" => O,
+        "P" => P,
+        "Q" => Q,
+        "R" => R,
+        "S" => S,
+        "T" => T,
+        "U" => U,
+        "V" => V,
+        "W" => W,
+        "X" => X,
+        "Y" => Y,
+        "Z" => Z,
+        "F1" => F1,
+        "F2" => F2,
+        "F3" => F3,
+        "F4" => F4,
+        "F5" => F5,
+        "F6" => F6,
+        "F7" => F7,
+        "F8" => F8,
+        "F9" => F9,
+        "F10" => F10,
+        "F11" => F11,
+        "F12" => F12,
+        "Escape" => Escape,
+        "Tab" => Tab,
+        "Space" => Space,
+        "Return" => Return,
+        "Back" => Back,
+        "Left" => Left,
+        "Up" => Up,
+        "Right" => Right,
+        "Down" => Down,
+        "LShift" => LShift,
+        "RShift" => RShift,
+        "LControl" => LControl,
+        "RControl" => RControl,
+        "LAlt" => LAlt,
+        "RAlt" => RAlt,
+        "LBracket" => LBracket,
+        "RBracket" => RBracket,
+        "Plus" => Plus,
+        "Minus" => Minus,
+        _ => return None,
+    };
+    Some(key)
 }
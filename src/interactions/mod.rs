@@ -1,45 +1,212 @@
-use crate::bevy_rapier_utils::*;
+mod registry;
+
+use crate::{
+    bevy_rapier_utils::*,
+    collision_layer::CollisionLayer,
+    commands_ext::CommandsExt,
+    components::{Facing, Marker},
+    data_asset::DataAssetPlugin,
+    entities::Caption,
+    replay::GameTime,
+};
 use bevy::prelude::*;
-use rand::prelude::*;
+
+pub use registry::*;
+
+/// Resolves `GameInteraction::Interact` through the `InteractionRegistry`.
+/// `Read` shows its text as a `Caption`, stations are left to the level through `InteractionEvent`s.
+/// Needs `GameTime`, see `ReplayPlugin`, and the `JournalPlugin`.
+pub struct InteractionsPlugin;
+
+impl Plugin for InteractionsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(DataAssetPlugin::<InteractionRegistry>::default())
+            .add_event::<GameInteraction>()
+            .add_event::<InteractionEvent>()
+            .add_system(interactions_system.system())
+            .add_system(sleeping_system.system());
+    }
+}
 
 pub enum GameInteraction {
-    PushAway(PushAway),
+    /// `actor` interacts with `which`, depending on the marker of `which`.
+    Interact { actor: Entity, which: Entity },
 }
 
+/// How `Push` interactions move things, see `push_away`.
 pub struct PushAway {
     pub which: Entity,
     pub relative_to: Entity,
     pub rel_impulse: f32,
+    /// Pushes along this direction instead of away from `relative_to`.
+    pub direction: Option<Vec2>,
+}
+
+/// Sent for every interaction that happened, for stations, prompts and the like.
+#[derive(Debug, Clone)]
+pub struct InteractionEvent {
+    pub actor: Entity,
+    pub which: Entity,
+    pub kind: InteractionKind,
 }
 
+/// Door like things, open ones keep a sensor collider so they can be closed again.
+#[derive(Debug, Clone, Copy)]
+pub struct Openable {
+    pub open: bool,
+    pub half_extents: Vec2,
+}
+
+/// Actors that sleep can not act until the timer finished.
+pub struct Sleeping(pub Timer);
+
 pub fn interactions_system(
-    _commands: &mut Commands,
+    commands: &mut Commands,
     mut reader: Local<EventReader<GameInteraction>>,
     events: Res<Events<GameInteraction>>,
+    registry: Res<InteractionRegistry>,
+    mut outcomes: ResMut<Events<InteractionEvent>>,
+    mut captions: ResMut<Events<Caption>>,
     mut bodies: ResMut<RigidBodySet>,
     body: Query<&RigidBodyHandleComponent>,
+    markers: Query<&Marker>,
+    facings: Query<&Facing>,
+    mut openables: Query<Mut<Openable>>,
+    children: Query<&Children>,
+    colliders: Query<&ColliderHandleComponent>,
+    mut sprites: Query<Mut<TextureAtlasSprite>>,
 ) {
     for effect in reader.iter(&events) {
         match effect {
-            GameInteraction::PushAway(push) => push_away(push, &mut bodies, &body),
+            GameInteraction::Interact { actor, which } => {
+                let kind = match markers.get(*which).ok().and_then(|it| registry.get(*it)) {
+                    Some(kind) => kind.clone(),
+                    None => continue,
+                };
+
+                match &kind {
+                    InteractionKind::Toggle => {
+                        for mut openable in openables.get_mut(*which) {
+                            openable.open = !openable.open;
+                            show_openable(
+                                commands,
+                                *which,
+                                &openable,
+                                &children,
+                                &colliders,
+                                &mut sprites,
+                            );
+                        }
+                    }
+                    InteractionKind::Push { speed } => {
+                        let push = PushAway {
+                            which: *which,
+                            relative_to: *actor,
+                            rel_impulse: *speed,
+                            direction: facings.get(*actor).ok().map(|it| it.0),
+                        };
+                        push_away(&push, &mut bodies, &body);
+                    }
+                    InteractionKind::Sleep { seconds } => {
+                        commands.insert_one(*actor, Sleeping(Timer::from_seconds(*seconds, false)));
+                    }
+                    InteractionKind::Read { text } => captions.send(Caption(text.clone())),
+                    // see `InteractionEvent`
+                    InteractionKind::Station { .. } => {}
+                }
+
+                outcomes.send(InteractionEvent {
+                    actor: *actor,
+                    which: *which,
+                    kind,
+                });
+            }
         }
     }
 }
 
+/// Updates collider and sprite of `entity` after its `Openable` was opened or closed.
+pub fn show_openable(
+    commands: &mut Commands,
+    entity: Entity,
+    openable: &Openable,
+    children: &Query<&Children>,
+    colliders: &Query<&ColliderHandleComponent>,
+    sprites: &mut Query<Mut<TextureAtlasSprite>>,
+) {
+    toggle_collider(commands, entity, openable, children, colliders);
+
+    for mut sprite in sprites.get_mut(entity) {
+        sprite.color.set_a(if openable.open { 0.4 } else { 1.0 });
+    }
+}
+
+/// Replaces the collider children of `entity`, solid when closed and a sensor when open.
+fn toggle_collider(
+    commands: &mut Commands,
+    entity: Entity,
+    openable: &Openable,
+    children: &Query<&Children>,
+    colliders: &Query<&ColliderHandleComponent>,
+) {
+    for children in children.get(entity) {
+        for child in children.iter().filter(|it| colliders.get(**it).is_ok()) {
+            commands.despawn_recursive(*child);
+        }
+    }
+
+    let collider = commands.entity((ColliderBuilder::cuboid(
+        openable.half_extents.x,
+        openable.half_extents.y,
+    )
+    .user_data(entity.to_user_data())
+    .collision_groups(CollisionLayer::Wall.groups())
+    .sensor(openable.open),));
+    commands.push_children(entity, &[collider]);
+}
+
+/// Sets the velocity of `which` to `rel_impulse`, pointing away from `relative_to`
+/// or along `direction`.
 pub fn push_away(
     push: &PushAway,
     bodies: &mut ResMut<RigidBodySet>,
     body: &Query<&RigidBodyHandleComponent>,
 ) {
+    let position = |entity| {
+        let body = bodies.get(body.get(entity).ok()?.handle())?;
+        let translation = body.position().translation;
+        Some(Vec2::new(translation.x, translation.y))
+    };
+
+    let dir = match (
+        push.direction,
+        position(push.which),
+        position(push.relative_to),
+    ) {
+        (Some(direction), _, _) => direction.normalize(),
+        (None, Some(which), Some(relative_to)) if which != relative_to => {
+            (which - relative_to).normalize()
+        }
+        _ => return,
+    };
+
     if let Some(body) = body
         .get(push.which)
         .ok()
         .and_then(|body| bodies.get_mut(body.handle()))
     {
-        let mut rng = rand::thread_rng();
-        let dir = (push.rel_impulse
-            * Vec2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)).normalize())
-        .into_vector2();
-        body.set_linvel(dir, true);
+        body.set_linvel((push.rel_impulse * dir).into_vector2(), true);
+    }
+}
+
+pub fn sleeping_system(
+    commands: &mut Commands,
+    time: Res<GameTime>,
+    mut query: Query<(Entity, Mut<Sleeping>)>,
+) {
+    for (entity, mut sleeping) in query.iter_mut() {
+        if sleeping.0.tick(time.delta_seconds()).finished() {
+            commands.remove_one::<Sleeping>(entity);
+        }
     }
 }
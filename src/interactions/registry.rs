@@ -0,0 +1,173 @@
+use bevy::{reflect::TypeUuid, utils::HashMap};
+
+use crate::{components::Marker, data_asset::DataAsset};
+
+#[test]
+fn parse_interactions() {
+    let registry = InteractionRegistry::parse(
+        "# comment\n\
+         Door = Toggle\n\
+         Chair = Push 120\n\
+         Bookshelf = Read Dusty books about fish.\n",
+    )
+    .unwrap();
+
+    assert_eq!(registry.get(Marker::Door), Some(&InteractionKind::Toggle));
+    assert_eq!(
+        registry.get(Marker::Chair),
+        Some(&InteractionKind::Push { speed: 120.0 })
+    );
+    assert_eq!(
+        registry.get(Marker::Bookshelf),
+        Some(&InteractionKind::Read {
+            text: "Dusty books about fish.".into()
+        })
+    );
+    assert_eq!(registry.get(Marker::Wall), None);
+    assert!(InteractionRegistry::parse("Door = Fly").is_err());
+    assert!(InteractionRegistry::parse("Dragon = Toggle").is_err());
+}
+
+pub const INTERACTIONS_PATH: &'static str = "game.interactions";
+
+/// What happens when an actor interacts with a marked entity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InteractionKind {
+    /// Opens or closes, an open `Openable` lets everything pass.
+    Toggle,
+    /// Sets the velocity of the entity to `speed`, along the facing of the actor
+    /// or away from it.
+    Push {
+        speed: f32,
+    },
+    /// The actor sleeps and can not act for `seconds`.
+    Sleep {
+        seconds: f32,
+    },
+    Read {
+        text: String,
+    },
+    /// Use of a station like an oven, handled by whoever listens to `InteractionEvent`.
+    Station {
+        name: String,
+    },
+}
+
+impl InteractionKind {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().splitn(2, ' ');
+        let name = parts.next()?;
+        let arg = parts.next().unwrap_or("").trim();
+
+        let kind = match name {
+            "Toggle" => InteractionKind::Toggle,
+            "Push" => InteractionKind::Push {
+                speed: arg.parse().ok()?,
+            },
+            "Sleep" => InteractionKind::Sleep {
+                seconds: arg.parse().ok()?,
+            },
+            "Read" => InteractionKind::Read { text: arg.into() },
+            "Station" => InteractionKind::Station { name: arg.into() },
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Prompts prefer interactions with a higher priority over closer ones.
+    pub fn priority(&self) -> u8 {
+        match self {
+            InteractionKind::Station { .. } => 3,
+            InteractionKind::Toggle => 2,
+            InteractionKind::Read { .. } | InteractionKind::Sleep { .. } => 1,
+            InteractionKind::Push { .. } => 0,
+        }
+    }
+
+    /// Verb to show in prompts.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            InteractionKind::Toggle => "Open",
+            InteractionKind::Push { .. } => "Push",
+            InteractionKind::Sleep { .. } => "Sleep",
+            InteractionKind::Read { .. } => "Read",
+            InteractionKind::Station { .. } => "Use",
+        }
+    }
+}
+
+/// Interactions per marker, loaded from `assets/game.interactions`,
+/// lines like `Chair = Push 120`.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "39cadc56-aa9c-4543-8640-a018b71b5054"]
+pub struct InteractionRegistry {
+    interactions: HashMap<Marker, InteractionKind>,
+}
+
+impl Default for InteractionRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            interactions: HashMap::default(),
+        };
+        registry
+            .register(Marker::Door, InteractionKind::Toggle)
+            .register(Marker::Chair, InteractionKind::Push { speed: 120.0 })
+            .register(Marker::Bed, InteractionKind::Sleep { seconds: 4.0 })
+            .register(
+                Marker::Bookshelf,
+                InteractionKind::Read {
+                    text: "Dusty books about ovens and fish.".into(),
+                },
+            )
+            .register(
+                Marker::Oven,
+                InteractionKind::Station {
+                    name: "oven".into(),
+                },
+            );
+        registry
+    }
+}
+
+impl InteractionRegistry {
+    pub fn register(&mut self, marker: Marker, kind: InteractionKind) -> &mut Self {
+        self.interactions.insert(marker, kind);
+        self
+    }
+
+    pub fn get(&self, marker: Marker) -> Option<&InteractionKind> {
+        self.interactions.get(&marker)
+    }
+}
+
+impl DataAsset for InteractionRegistry {
+    const PATH: &'static str = INTERACTIONS_PATH;
+    const EXTENSIONS: &'static [&'static str] = &["interactions"];
+
+    fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut registry = Self {
+            interactions: HashMap::default(),
+        };
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let marker = parts.next().unwrap_or("").trim();
+            let kind = parts.next().unwrap_or("");
+
+            let marker = Marker::parse(marker).ok_or_else(|| {
+                anyhow::anyhow!("line {}: unknown marker {:?}", index + 1, marker)
+            })?;
+            let kind = InteractionKind::parse(kind).ok_or_else(|| {
+                anyhow::anyhow!("line {}: invalid interaction {:?}", index + 1, kind)
+            })?;
+            registry.register(marker, kind);
+        }
+
+        Ok(registry)
+    }
+}
@@ -0,0 +1,606 @@
+use std::path::PathBuf;
+
+use bevy::{
+    asset::{AssetServerSettings, FileAssetIo},
+    prelude::*,
+    render::camera::Camera,
+};
+use bevy_prototype_lyon::prelude::{
+    point, primitive, ShapeType as LyonShape, StrokeOptions, TessellationMode,
+};
+
+use crate::{
+    entities::Caption,
+    input::{ActionState, InputAction, InputCapture},
+    levels::level2::{TileMap, TileMapSpawner, DEFAULT_LAYER},
+    utils::{camera_2d_transform, screen_to_world},
+};
+
+/// Paints the tilemap of the current level with the mouse and writes it back to its file.
+///
+/// The `ToggleEditor` action (F2) opens the editor, `EditorNextLayer` (Tab) picks the layer,
+/// `EditorPrevTile` and `EditorNextTile` (`[` and `]`) pick a tile from the palette,
+/// the left mouse button paints and the right one erases. `Undo` and `Redo` (Ctrl+Z and Ctrl+Y)
+/// take back a stroke, `SaveTileMap` (Ctrl+S) saves the `.tilemap` file, the outcome is shown
+/// as a `Caption`. While it is open the editor captures the input, see `InputCapture`.
+/// Needs the `InputMapPlugin` and the `JournalPlugin`.
+/// Every change goes through the `TileMap` asset, so the spawners diff and respawn their
+/// tiles the same way as after an edit of the file.
+pub struct TileMapEditorPlugin;
+
+impl Plugin for TileMapEditorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TileMapEditor>()
+            .add_system(tilemap_editor_toggle_system.system())
+            .add_system(tilemap_editor_keys_system.system())
+            .add_system(tilemap_editor_paint_system.system())
+            .add_system(tilemap_editor_ui_system.system());
+    }
+}
+
+#[test]
+fn edit_history_undo_redo() {
+    let mut tilemap = TileMap::parse(b"[floor 0]\n...\n[walls 1]\n#D#\n# c\n");
+    let mut history = EditHistory::default();
+
+    assert!(history.paint(&mut tilemap, 1, 1, 1, Some('=')));
+    assert!(history.paint(&mut tilemap, 1, 2, 1, None));
+    assert!(!history.paint(&mut tilemap, 1, 2, 1, None));
+    history.finish_stroke();
+    assert!(history.paint(&mut tilemap, 0, 1, 1, Some('.')));
+    history.finish_stroke();
+    assert_eq!(
+        tilemap.to_text(),
+        "[floor 0]\n...\n .\n[walls 1]\n#D#\n#=\n"
+    );
+
+    assert!(history.undo(&mut tilemap));
+    assert!(history.undo(&mut tilemap));
+    assert!(!history.undo(&mut tilemap));
+    assert_eq!(tilemap.to_text(), "[floor 0]\n...\n[walls 1]\n#D#\n# c\n");
+
+    assert!(history.redo(&mut tilemap));
+    assert_eq!(tilemap.to_text(), "[floor 0]\n...\n[walls 1]\n#D#\n#=\n");
+
+    history.paint(&mut tilemap, 1, 0, 0, Some('W'));
+    history.finish_stroke();
+    assert!(!history.redo(&mut tilemap));
+}
+
+#[test]
+fn edits_respawn_tiles_through_the_spawner() {
+    use crate::levels::level2::{sync_tilemap_spawner_system, Tile, TileLayer, TileMapSpawnEvent};
+
+    fn spawn_tiles_system(
+        commands: &mut Commands,
+        mut event_reader: Local<EventReader<TileMapSpawnEvent>>,
+        events: Res<Events<TileMapSpawnEvent>>,
+    ) {
+        for event in event_reader.iter(&events) {
+            match event {
+                TileMapSpawnEvent::Spawn(bundle) => commands.spawn(*bundle),
+                TileMapSpawnEvent::Despawn(a_tile) => commands.despawn(*a_tile),
+            };
+        }
+    }
+
+    /// The tiles after the spawner caught up, the asset event arrives a frame later.
+    fn tiles(app: &mut App) -> Vec<(usize, char, u32)> {
+        app.update();
+        app.update();
+        let mut tiles: Vec<_> = app
+            .world
+            .query::<(&TileLayer, &Tile)>()
+            .map(|(layer, tile)| (layer.0, tile.0 as char, tile.1))
+            .collect();
+        tiles.sort_by_key(|&(layer, _, col)| (layer, col));
+        tiles
+    }
+
+    let mut builder = App::build();
+    builder
+        .add_plugin(bevy::core::CorePlugin)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_asset::<TileMap>()
+        .add_event::<TileMapSpawnEvent>()
+        .add_system(sync_tilemap_spawner_system.system())
+        .add_system(spawn_tiles_system.system());
+    let mut app = builder.app;
+
+    let handle = app
+        .resources
+        .get_mut::<Assets<TileMap>>()
+        .unwrap()
+        .add(TileMap::parse(b"[floor 0]\n..\n[walls 1]\n#D#\n"));
+    app.update();
+    app.world
+        .spawn((TileMapSpawner::new(handle.clone()), Transform::default()));
+    let before = vec![
+        (0, '.', 0),
+        (0, '.', 1),
+        (1, '#', 0),
+        (1, 'D', 1),
+        (1, '#', 2),
+    ];
+    assert_eq!(tiles(&mut app), before);
+
+    let mut history = EditHistory::default();
+    let mut edit = |app: &mut App, change: &dyn Fn(&mut EditHistory, &mut TileMap)| {
+        let mut tilemaps = app.resources.get_mut::<Assets<TileMap>>().unwrap();
+        change(&mut history, tilemaps.get_mut(&handle).unwrap());
+    };
+
+    // the floor under the changed wall stays
+    edit(&mut app, &|history, tilemap| {
+        history.paint(tilemap, 1, 1, 0, Some('W'));
+        history.paint(tilemap, 1, 2, 0, None);
+        history.finish_stroke();
+    });
+    assert_eq!(
+        tiles(&mut app),
+        vec![(0, '.', 0), (0, '.', 1), (1, '#', 0), (1, 'W', 1)]
+    );
+
+    edit(&mut app, &|history, tilemap| {
+        history.undo(tilemap);
+    });
+    assert_eq!(tiles(&mut app), before);
+}
+
+/// The tiles a level offers in the editor, put on its `TileMapSpawner`.
+/// Without it the palette holds the tiles found in the map.
+#[derive(Debug, Clone, Default)]
+pub struct TileMapPalette(pub Vec<char>);
+
+/// One cell changed by the editor, `None` is no tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileEdit {
+    pub layer: usize,
+    pub col: u32,
+    pub row: u32,
+    pub before: Option<char>,
+    pub after: Option<char>,
+}
+
+/// Undo and redo by stroke, a stroke is everything painted while a mouse button was held.
+#[derive(Debug, Clone, Default)]
+pub struct EditHistory {
+    undo: Vec<Vec<TileEdit>>,
+    redo: Vec<Vec<TileEdit>>,
+    stroke: Vec<TileEdit>,
+}
+
+impl EditHistory {
+    /// Changes a cell as part of the current stroke, `false` if it already was `tile`.
+    pub fn paint(
+        &mut self,
+        tilemap: &mut TileMap,
+        layer: usize,
+        col: u32,
+        row: u32,
+        tile: Option<char>,
+    ) -> bool {
+        let before = tilemap.set(layer, col, row, tile);
+        if before == tile {
+            return false;
+        }
+        self.stroke.push(TileEdit {
+            layer,
+            col,
+            row,
+            before,
+            after: tile,
+        });
+        true
+    }
+
+    /// Ends the current stroke, what was undone before can not be redone anymore.
+    pub fn finish_stroke(&mut self) {
+        if !self.stroke.is_empty() {
+            self.undo.push(std::mem::take(&mut self.stroke));
+            self.redo.clear();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || !self.stroke.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty() && self.stroke.is_empty()
+    }
+
+    pub fn undo(&mut self, tilemap: &mut TileMap) -> bool {
+        self.finish_stroke();
+        match self.undo.pop() {
+            Some(stroke) => {
+                for edit in stroke.iter().rev() {
+                    tilemap.set(edit.layer, edit.col, edit.row, edit.before);
+                }
+                self.redo.push(stroke);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, tilemap: &mut TileMap) -> bool {
+        self.finish_stroke();
+        match self.redo.pop() {
+            Some(stroke) => {
+                for edit in stroke.iter() {
+                    tilemap.set(edit.layer, edit.col, edit.row, edit.after);
+                }
+                self.undo.push(stroke);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The actions the editor keeps while it captures the input.
+const EDITOR_ACTIONS: [InputAction; 7] = [
+    InputAction::ToggleEditor,
+    InputAction::EditorNextLayer,
+    InputAction::EditorPrevTile,
+    InputAction::EditorNextTile,
+    InputAction::Undo,
+    InputAction::Redo,
+    InputAction::SaveTileMap,
+];
+
+pub struct TileMapEditor {
+    pub enabled: bool,
+    /// The layer painted on, an index into `TileMap::layers`.
+    pub layer: usize,
+    pub palette: Vec<char>,
+    pub selected: usize,
+    pub history: EditHistory,
+    /// The spawner whose map is edited, the first one found when the editor opened.
+    spawner: Option<Entity>,
+    layer_names: Vec<String>,
+    cursor_cell: Option<(u32, u32)>,
+    unsaved: bool,
+    cursor: Option<Entity>,
+    ui: Vec<Entity>,
+}
+
+impl Default for TileMapEditor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            layer: 0,
+            palette: vec![],
+            selected: 0,
+            history: EditHistory::default(),
+            spawner: None,
+            layer_names: vec![],
+            cursor_cell: None,
+            unsaved: false,
+            cursor: None,
+            ui: vec![],
+        }
+    }
+}
+
+impl TileMapEditor {
+    pub fn selected_tile(&self) -> Option<char> {
+        self.palette.get(self.selected).copied()
+    }
+
+    fn status(&self) -> String {
+        let layer = self
+            .layer_names
+            .get(self.layer)
+            .map_or(DEFAULT_LAYER, |it| it.as_str());
+        let palette = self
+            .palette
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if i == self.selected {
+                    format!("[{}]", c)
+                } else {
+                    format!(" {} ", c)
+                }
+            })
+            .collect::<String>();
+        let cell = self
+            .cursor_cell
+            .map_or(String::new(), |(col, row)| format!("  {},{}", col, row));
+        let unsaved = if self.unsaved { "  unsaved" } else { "" };
+        format!("editor {} {}{}{}", layer, palette, cell, unsaved)
+    }
+}
+
+struct EditorCursor;
+
+/// The file an asset was loaded from, resolved from the same root and asset folder
+/// as the `AssetServer`, which is the manifest dir under cargo or the executable's dir.
+fn asset_file(
+    asset_server: &AssetServer,
+    settings: &AssetServerSettings,
+    handle: &Handle<TileMap>,
+) -> Option<PathBuf> {
+    let path = asset_server.get_handle_path(handle)?;
+    let root = FileAssetIo::get_root_path().join(&settings.asset_folder);
+    Some(root.join(path.path()))
+}
+
+fn tilemap_editor_toggle_system(
+    commands: &mut Commands,
+    actions: Res<ActionState>,
+    asset_server: Res<AssetServer>,
+    mut editor: ResMut<TileMapEditor>,
+    mut capture: ResMut<InputCapture>,
+    mut captions: ResMut<Events<Caption>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tilemaps: Res<Assets<TileMap>>,
+    spawners: Query<(Entity, &TileMapSpawner, Option<&TileMapPalette>)>,
+    cameras: Query<&Camera>,
+) {
+    // the level was left, its cleanup took the cursor and the ui with it
+    if editor.enabled && editor.spawner.map_or(true, |it| spawners.get(it).is_err()) {
+        editor.enabled = false;
+        editor.cursor = None;
+        editor.ui.clear();
+        capture.active = false;
+    }
+
+    if !actions.just_pressed(InputAction::ToggleEditor) {
+        return;
+    }
+
+    if editor.enabled {
+        editor.enabled = false;
+        capture.active = false;
+        let cursor = editor.cursor.take();
+        let ui = std::mem::take(&mut editor.ui);
+        for entity in cursor.into_iter().chain(ui) {
+            commands.despawn_recursive(entity);
+        }
+        return;
+    }
+
+    let (a_spawner, spawner, palette) = match spawners.iter().next() {
+        Some(it) => it,
+        None => {
+            captions.send(Caption("No tilemap to edit".into()));
+            return;
+        }
+    };
+    if editor.spawner != Some(a_spawner) {
+        editor.history = EditHistory::default();
+        editor.unsaved = false;
+    }
+    editor.spawner = Some(a_spawner);
+    let tilemap = tilemaps.get(spawner.handle());
+    editor.layer_names = tilemap.map_or(vec![], |it| {
+        it.layers().iter().map(|layer| layer.name.clone()).collect()
+    });
+    editor.layer = editor.layer.min(editor.layer_names.len().saturating_sub(1));
+    editor.palette = match (palette, tilemap) {
+        (Some(palette), _) => palette.0.clone(),
+        (None, Some(tilemap)) => {
+            let mut tiles: Vec<char> = tilemap.tiles().map(|(_, tile)| tile.0 as char).collect();
+            tiles.sort();
+            tiles.dedup();
+            tiles
+        }
+        (None, None) => vec![],
+    };
+    editor.selected = editor.selected.min(editor.palette.len().saturating_sub(1));
+    editor.enabled = true;
+    capture.active = true;
+    capture.keep = EDITOR_ACTIONS.to_vec();
+
+    let size = spawner.cell_size() * 0.5;
+    let outline = vec![
+        point(-size.x, -size.y),
+        point(size.x, -size.y),
+        point(size.x, size.y),
+        point(-size.x, size.y),
+    ];
+    let cursor = commands
+        .spawn(primitive(
+            materials.add(Color::YELLOW.into()),
+            &mut meshes,
+            LyonShape::Polyline {
+                points: outline,
+                closed: true,
+            },
+            TessellationMode::Stroke(&StrokeOptions::default().with_line_width(1.0)),
+            Vec3::new(0.0, 0.0, 100.0),
+        ))
+        .with(EditorCursor)
+        .current_entity()
+        .unwrap();
+    commands.push_children(a_spawner, &[cursor]);
+    editor.cursor = Some(cursor);
+
+    let ui_camera_name = CameraUiBundle::default().camera.name;
+    if !cameras.iter().any(|camera| camera.name == ui_camera_name) {
+        let camera = commands.spawn(CameraUiBundle::default()).current_entity();
+        editor.ui.extend(camera);
+    }
+    let text = commands
+        .spawn(TextBundle {
+            text: Text {
+                value: editor.status(),
+                font: asset_server.load("FiraSans-Bold.ttf"),
+                style: TextStyle {
+                    font_size: 24.0,
+                    color: Color::YELLOW,
+                    ..Default::default()
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(8.0),
+                    top: Val::Px(8.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .current_entity();
+    editor.ui.extend(text);
+}
+
+fn tilemap_editor_keys_system(
+    actions: Res<ActionState>,
+    asset_server: Res<AssetServer>,
+    asset_settings: Res<AssetServerSettings>,
+    mut editor: ResMut<TileMapEditor>,
+    mut captions: ResMut<Events<Caption>>,
+    mut tilemaps: ResMut<Assets<TileMap>>,
+    spawners: Query<&TileMapSpawner>,
+) {
+    if !editor.enabled {
+        return;
+    }
+
+    let layers = editor.layer_names.len();
+    if layers > 0 && actions.just_pressed(InputAction::EditorNextLayer) {
+        editor.layer = (editor.layer + 1) % layers;
+    }
+
+    let count = editor.palette.len();
+    if count > 0 {
+        if actions.just_pressed(InputAction::EditorPrevTile) {
+            editor.selected = (editor.selected + count - 1) % count;
+        }
+        if actions.just_pressed(InputAction::EditorNextTile) {
+            editor.selected = (editor.selected + 1) % count;
+        }
+    }
+
+    let handle = match editor.spawner.and_then(|it| spawners.get(it).ok()) {
+        Some(spawner) => spawner.handle().clone(),
+        None => return,
+    };
+    let editor = &mut *editor;
+    if actions.just_pressed(InputAction::Undo) && editor.history.can_undo() {
+        if let Some(tilemap) = tilemaps.get_mut(&handle) {
+            editor.unsaved |= editor.history.undo(tilemap);
+        }
+    }
+    if actions.just_pressed(InputAction::Redo) && editor.history.can_redo() {
+        if let Some(tilemap) = tilemaps.get_mut(&handle) {
+            editor.unsaved |= editor.history.redo(tilemap);
+        }
+    }
+    if actions.just_pressed(InputAction::SaveTileMap) {
+        let path = asset_file(&asset_server, &asset_settings, &handle);
+        let (tilemap, path) = match (tilemaps.get(&handle), path) {
+            (Some(tilemap), Some(path)) => (tilemap, path),
+            _ => return,
+        };
+        match std::fs::write(&path, tilemap.to_text()) {
+            Ok(()) => {
+                editor.unsaved = false;
+                captions.send(Caption("Tilemap saved".into()));
+            }
+            Err(err) => {
+                bevy::log::warn!("could not save {}: {}", path.display(), err);
+                captions.send(Caption("Could not save the tilemap".into()));
+            }
+        }
+    }
+}
+
+fn tilemap_editor_paint_system(
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    mut editor: ResMut<TileMapEditor>,
+    mut tilemaps: ResMut<Assets<TileMap>>,
+    camera_query: Query<(&Camera, &Transform)>,
+    spawners: Query<(&TileMapSpawner, &GlobalTransform)>,
+) {
+    if !editor.enabled {
+        return;
+    }
+    let (spawner, spawner_transform) = match editor.spawner.and_then(|it| spawners.get(it).ok()) {
+        Some(it) => it,
+        None => return,
+    };
+
+    let window = windows.get_primary();
+    let cursor = window.and_then(|it| it.cursor_position());
+    let camera_transform = camera_2d_transform(&camera_query);
+    editor.cursor_cell = match (window, cursor, camera_transform) {
+        (Some(window), Some(cursor), Some(camera_transform)) => {
+            let world = screen_to_world(window, cursor, camera_transform);
+            let local =
+                spawner_transform.compute_matrix().inverse() * world.extend(0.0).extend(1.0);
+            spawner.cell_at(local.truncate().truncate())
+        }
+        _ => None,
+    };
+
+    let tile = if mouse.pressed(MouseButton::Left) {
+        match editor.selected_tile() {
+            Some(tile) => Some(tile),
+            None => return,
+        }
+    } else if mouse.pressed(MouseButton::Right) {
+        None
+    } else {
+        editor.history.finish_stroke();
+        return;
+    };
+
+    let (col, row) = match editor.cursor_cell {
+        Some(cell) => cell,
+        None => return,
+    };
+    let handle = spawner.handle();
+    // only a real change touches the asset, that is what makes the spawners diff
+    if tilemaps
+        .get(handle)
+        .map_or(true, |it| it.get(editor.layer, col, row) == tile)
+    {
+        return;
+    }
+    let editor = &mut *editor;
+    if let Some(tilemap) = tilemaps.get_mut(handle) {
+        editor.unsaved |= editor.history.paint(tilemap, editor.layer, col, row, tile);
+    }
+}
+
+fn tilemap_editor_ui_system(
+    editor: Res<TileMapEditor>,
+    spawners: Query<&TileMapSpawner>,
+    mut cursor_query: Query<(Mut<Transform>, Mut<Visible>), With<EditorCursor>>,
+    mut text_query: Query<Mut<Text>>,
+) {
+    if !editor.enabled {
+        return;
+    }
+
+    let spawner = editor.spawner.and_then(|it| spawners.get(it).ok());
+    for (mut transform, mut visible) in cursor_query.iter_mut() {
+        match (spawner, editor.cursor_cell) {
+            (Some(spawner), Some((col, row))) => {
+                let center = spawner.cell_center(col, row);
+                transform.translation.x = center.x;
+                transform.translation.y = center.y;
+                visible.is_visible = true;
+            }
+            _ => visible.is_visible = false,
+        }
+    }
+
+    let status = editor.status();
+    for &entity in editor.ui.iter() {
+        if let Ok(mut text) = text_query.get_mut(entity) {
+            if text.value != status {
+                text.value = status.clone();
+            }
+        }
+    }
+}
@@ -7,7 +7,7 @@
     Stone, Mage are markers
     CanBeItemBasics and CanItemBasics deals with picking, dropping, throwing
     SoundOnContact, ContactType, SoundType describes which sound to play when
-    Kinematics deals with physical movement of things
+    Velocity and Drag deal with physical movement of things, see systems::movement
     MovementAbility deals with the ability for physical movement
     ControlRandomMovement for controling MovementAbility things
     ControlRandomItemBasics for controling CanItemBasics things
@@ -18,27 +18,41 @@ use std::cmp::Ordering;
 use bevy::{ecs::DynamicBundle, prelude::*};
 use rand::prelude::*;
 
-use crate::bitpack::{Bitpack, BitpackPlugin};
+use crate::{
+    bitpack::Bitpack,
+    levels::{AppState, LevelBuilder, LevelInfo},
+    replay::GameTime,
+    rng::GameRng,
+    save::PendingLoad,
+    systems::camera::CameraController,
+};
+
+pub use crate::systems::{
+    contact_sound::{ContactType, SoundOnContact, SoundType},
+    movement::{Carried, Drag, MovementPlugin, Thrown, Velocity},
+};
 
 pub fn app() -> AppBuilder {
-    let mut app = App::build();
-    app.add_plugins(DefaultPlugins)
-        .add_plugin(Level1Plugin)
-        .add_plugin(BitpackPlugin);
-    app
+    super::app(Some("level1"))
 }
 
 pub struct Level1Plugin;
 
 impl Plugin for Level1Plugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(setup.system())
-            .add_startup_system(add_camera.system())
-            .add_system(kinematic_system.system())
-            .add_system(control_random_movement_system.system())
-            .add_system(control_random_item_basics_system.system())
-            .add_system(carry_system.system())
-            .add_system(throw_system.system());
+        LevelBuilder::new(
+            app,
+            LevelInfo {
+                state: AppState::Level1,
+                name: "level1",
+                title: "a stone and a mage",
+            },
+        )
+        .reset_resource_on_exit::<PendingLoad>()
+        .on_enter(setup.system())
+        .on_enter(add_camera.system())
+        .add_system(control_random_movement_system.system())
+        .add_system(control_random_item_basics_system.system());
     }
 }
 
@@ -58,32 +72,6 @@ pub struct CanItemBasics {
     pub picked_up: Option<Entity>,
 }
 
-pub struct SoundOnContact {
-    pub _sound_map: Vec<(ContactType, SoundType)>,
-}
-
-impl SoundOnContact {
-    pub fn new(sound_map: Vec<(ContactType, SoundType)>) -> Self {
-        Self {
-            _sound_map: sound_map,
-        }
-    }
-}
-
-pub enum ContactType {
-    Ground,
-    Wall,
-}
-pub enum SoundType {
-    Clonk,
-    Bling,
-}
-
-pub struct Kinematics {
-    pub vel: Vec3,
-    pub drag: f32,
-}
-
 pub struct MovementAbility {
     pub top_speed: f32,
 }
@@ -96,21 +84,6 @@ pub struct ControlRandomItemBasics {
     pub timer: Timer,
 }
 
-pub struct Carried {
-    pub owner: Entity,
-    pub offset: Transform,
-}
-
-pub struct Thrown {
-    pub vel: Vec3,
-}
-
-impl Thrown {
-    pub fn new(vel: Vec3) -> Self {
-        Self { vel }
-    }
-}
-
 pub fn setup(commands: &mut Commands, bitpack: Res<Bitpack>) {
     use ContactType::*;
     use SoundType::*;
@@ -122,10 +95,8 @@ pub fn setup(commands: &mut Commands, bitpack: Res<Bitpack>) {
             drop: true,
             throw: true,
         },
-        Kinematics {
-            vel: Vec3::zero(),
-            drag: 0.97,
-        },
+        Velocity::default(),
+        Drag(0.97),
         Transform::from_translation(Vec3::new(-32.0, 0.0, 0.0)),
         GlobalTransform::default(),
         SoundOnContact::new(vec![(Ground, Clonk), (Wall, Bling)]),
@@ -139,10 +110,8 @@ pub fn setup(commands: &mut Commands, bitpack: Res<Bitpack>) {
             throw: true,
             picked_up: None,
         },
-        Kinematics {
-            vel: Vec3::zero(),
-            drag: 0.97,
-        },
+        Velocity::default(),
+        Drag(0.97),
         MovementAbility { top_speed: 20.0 },
         Transform::default(),
         GlobalTransform::default(),
@@ -190,49 +159,43 @@ pub fn dress_mage(atlas: Handle<TextureAtlas>) -> impl DynamicBundle {
     }
 }
 
-pub fn kinematic_system(time: Res<Time>, mut query: Query<(Mut<Kinematics>, Mut<Transform>)>) {
-    let dt = time.delta_seconds();
-    for (mut kin, mut trans) in query.iter_mut() {
-        let drag = kin.drag;
-        kin.vel *= 1.0 - drag * dt;
-        trans.translation += kin.vel * dt;
-    }
-}
-
 pub fn control_random_movement_system(
-    time: Res<Time>,
-    mut query: Query<(
-        Mut<ControlRandomMovement>,
-        Mut<Kinematics>,
-        &MovementAbility,
-    )>,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
+    mut query: Query<(Mut<ControlRandomMovement>, Mut<Velocity>, &MovementAbility)>,
 ) {
     let dt = time.delta_seconds();
-    let mut rng = rand::thread_rng();
-    for (mut control, mut kin, movement) in query.iter_mut() {
+    let rng = rng.stream("random_movement");
+    for (mut control, mut velocity, movement) in query.iter_mut() {
         if control.timer.tick(dt).finished() {
             let top_speed = movement.top_speed;
             let rand_vec = rng.random_vec2d() * top_speed * 0.8;
-            kin.vel = rand_vec;
+            velocity.0 = rand_vec.truncate();
         }
     }
 }
 
 pub fn control_random_item_basics_system(
     commands: &mut Commands,
-    time: Res<Time>,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
     mut active_query: Query<(Entity, Mut<ControlRandomItemBasics>, Mut<CanItemBasics>)>,
-    can_be_item_query: Query<&CanBeItemBasics>,
+    can_be_item_query: Query<(&CanBeItemBasics, Option<&Carried>)>,
     not_carried_items: Query<(Entity, &CanBeItemBasics), Without<Carried>>,
 ) {
     let dt = time.delta_seconds();
+    let rng = rng.stream("random_item_basics");
     let mut pickups = None;
 
     for (owner, mut control, mut can) in active_query.iter_mut() {
         if control.timer.tick(dt).finished() {
             if let Some(item) = can.picked_up {
-                if let Ok(can_be) = can_be_item_query.get(item) {
-                    let mut rng = rand::thread_rng();
+                // a carried item that got stuck was dropped already
+                if let Some((can_be, _)) = can_be_item_query
+                    .get(item)
+                    .ok()
+                    .filter(|(_, carried)| carried.map_or(false, |it| it.owner == owner))
+                {
                     let drop = can.drop && can_be.drop;
                     let throw = can.throw && can_be.throw;
 
@@ -241,20 +204,21 @@ pub fn control_random_item_basics_system(
                         commands.remove_one::<Carried>(item);
                     } else if throw {
                         can.picked_up = None;
-                        commands.insert_one(item, Thrown::new(rng.random_vec2d() * 40.0));
+                        commands.insert_one(item, Thrown::by(owner, rng.random_vec2d() * 40.0));
                         commands.remove_one::<Carried>(item);
                     }
                 } else {
                     can.picked_up = None;
                 }
             } else if can.pick_up {
-                let pickups = pickups
-                    .get_or_insert_with(|| shuffled_pickable_items(&mut not_carried_items.iter()));
+                let pickups = pickups.get_or_insert_with(|| {
+                    shuffled_pickable_items(&mut not_carried_items.iter(), &mut *rng)
+                });
 
                 if let Some(item) = pickups.pop() {
                     let offset = Transform::from_translation(Vec3::new(0.0, 6.0, 0.0));
                     can.picked_up = Some(item.clone());
-                    commands.insert_one(item, Carried { owner, offset });
+                    commands.insert_one(item, Carried::new(owner, offset));
                 }
             }
         }
@@ -263,8 +227,8 @@ pub fn control_random_item_basics_system(
 
 fn shuffled_pickable_items(
     items: &mut dyn Iterator<Item = (Entity, &CanBeItemBasics)>,
+    rng: &mut impl Rng,
 ) -> Vec<Entity> {
-    let mut rng = rand::thread_rng();
     let mut pickups: Vec<Entity> = items.filter_map(|(e, c)| c.pick_up.then_some(e)).collect();
     pickups.sort_unstable_by(|_, _| {
         if rng.gen() {
@@ -276,40 +240,15 @@ fn shuffled_pickable_items(
     pickups
 }
 
-pub fn carry_system(
-    commands: &mut Commands,
-    mut query: Query<(Entity, &Carried, Mut<Transform>)>,
-    transform: Query<&Transform, Without<Carried>>,
-) {
-    for (item, carried, mut trans) in query.iter_mut() {
-        if let Ok(owner_trans) = transform.get(carried.owner) {
-            trans.translation = owner_trans.translation + carried.offset.translation;
-        } else {
-            commands.remove_one::<Carried>(item);
-        }
-    }
-}
-
-pub fn throw_system(commands: &mut Commands, mut query: Query<(Entity, &Thrown, Mut<Kinematics>)>) {
-    for (entity, thrown, mut kin) in query.iter_mut() {
-        kin.vel += thrown.vel;
-        commands.remove_one::<Thrown>(entity);
-    }
-}
-
 pub fn add_camera(commands: &mut Commands) {
-    commands.spawn({
-        let mut bundle = Camera2dBundle::default();
-        bundle.transform.scale = Vec3::new(0.25, 0.25, 1.0);
-        bundle
-    });
+    commands.spawn(CameraController::new(0.25).pixel_perfect(1.0).bundle());
 }
 
 pub trait RandomVec {
     fn random_vec2d(&mut self) -> Vec3;
 }
 
-impl RandomVec for rand::ThreadRng {
+impl<R: Rng + ?Sized> RandomVec for R {
     fn random_vec2d(&mut self) -> Vec3 {
         Vec3::new(-0.5 + self.gen::<f32>(), -0.5 + self.gen::<f32>(), 0.0).normalize()
     }
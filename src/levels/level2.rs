@@ -14,41 +14,43 @@ use bevy::{
 };
 use serde::Deserialize;
 
-use crate::bitpack::{Bitpack, BitpackPlugin};
+use crate::bitpack::Bitpack;
+use crate::levels::{editor::TileMapPalette, AppState, LevelBuilder, LevelInfo};
+use crate::save::PendingLoad;
 
 use crate::levels::level1::{self, *};
 
 pub fn app() -> AppBuilder {
-    let mut app = App::build();
-    app.add_plugins(DefaultPlugins)
-        .add_plugin(BitpackPlugin)
-        .add_plugin(Level2Plugin);
-    app
+    super::app(Some("level2"))
 }
 
+/// Needs the `TileMap` asset and `sync_tilemap_spawner_system`, see `levels::app`.
 pub struct Level2Plugin;
 
 impl Plugin for Level2Plugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(setup.system())
-            .add_startup_system(level1::add_camera.system())
-            .add_system(level1::kinematic_system.system())
-            .add_system(level1::control_random_movement_system.system())
-            .add_system(level1::control_random_item_basics_system.system())
-            .add_system(level1::carry_system.system())
-            .add_system(level1::throw_system.system())
-            .add_system(sync_tilemap_spawner_system.system())
-            .add_system(EntityFactory::system.system())
-            .add_asset::<TileMap>()
-            .init_asset_loader::<TileMapLoader>()
-            .add_event::<TileMapSpawnEvent>();
+        LevelBuilder::new(
+            app,
+            LevelInfo {
+                state: AppState::Level2,
+                name: "level2",
+                title: "stones and mages from a tilemap",
+            },
+        )
+        .reset_resource_on_exit::<PendingLoad>()
+        .on_enter(setup.system())
+        .on_enter(level1::add_camera.system())
+        .add_system(level1::control_random_movement_system.system())
+        .add_system(level1::control_random_item_basics_system.system())
+        .add_system(EntityFactory::system.system());
     }
 }
 
 pub fn mage_bundle() -> (
     Mage,
     CanItemBasics,
-    Kinematics,
+    Velocity,
+    Drag,
     MovementAbility,
     ControlRandomMovement,
     ControlRandomItemBasics,
@@ -61,10 +63,8 @@ pub fn mage_bundle() -> (
             throw: true,
             picked_up: None,
         },
-        Kinematics {
-            vel: Vec3::zero(),
-            drag: 0.97,
-        },
+        Velocity::default(),
+        Drag(0.97),
         MovementAbility { top_speed: 20.0 },
         ControlRandomMovement {
             timer: Timer::from_seconds(1.0, true),
@@ -75,6 +75,9 @@ pub fn mage_bundle() -> (
     )
 }
 
+/// The tiles `EntityFactory` knows, mages, stones and trees.
+pub const TILE_PALETTE: &[char] = &['M', '.', 'A', 'a'];
+
 pub struct EntityFactory;
 
 impl EntityFactory {
@@ -100,10 +103,8 @@ impl EntityFactory {
                     drop: true,
                     throw: true,
                 },
-                Kinematics {
-                    vel: Vec3::zero(),
-                    drag: 0.97,
-                },
+                Velocity::default(),
+                Drag(0.97),
                 SoundOnContact::new(vec![(Ground, Clonk), (Wall, Bling)]),
             ))
             .with_children(|child| {
@@ -157,14 +158,13 @@ impl EntityFactory {
 }
 
 pub fn setup(commands: &mut Commands, asset_server: Res<AssetServer>) {
-    asset_server.watch_for_changes().unwrap();
-
     let tilemap_handle: Handle<TileMap> = asset_server.load("level2.tilemap");
 
     let level2_tilemap = (
         Transform::from_translation(Vec3::new(-64.0, 64.0, 0.0)),
         GlobalTransform::default(),
         TileMapSpawner::new(tilemap_handle),
+        TileMapPalette(TILE_PALETTE.to_vec()),
         Children::default(),
     );
 
@@ -186,22 +186,54 @@ impl TileMapSpawner {
         }
     }
 
-    pub fn spawn(a_spawner: Entity, spawner: &TileMapSpawner, tile: &Tile) -> TileMapSpawnEvent {
+    pub fn spawn(
+        a_spawner: Entity,
+        spawner: &TileMapSpawner,
+        tilemap: &TileMap,
+        layer: TileLayer,
+        tile: &Tile,
+    ) -> TileMapSpawnEvent {
+        let z = tilemap.layers.get(layer.0).map_or(0.0, |it| it.z);
         TileMapSpawnEvent::Spawn((
             *tile,
             Parent(a_spawner),
-            Transform::from_translation(Vec3::new(
-                spawner.width * tile.1 as f32,
-                -spawner.height * tile.2 as f32,
-                0.0,
-            )),
+            Transform::from_translation(spawner.cell_center(tile.1, tile.2).extend(z)),
             GlobalTransform::default(),
+            layer,
         ))
     }
 
     pub fn despawn(a_tile: Entity) -> TileMapSpawnEvent {
         TileMapSpawnEvent::Despawn(a_tile)
     }
+    pub fn handle(&self) -> &Handle<TileMap> {
+        &self.handle
+    }
+
+    pub fn cell_size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height)
+    }
+
+    /// Where the tile of a cell is spawned, relative to the spawner.
+    pub fn cell_center(&self, col: u32, row: u32) -> Vec2 {
+        Vec2::new(self.width * col as f32, -self.height * row as f32)
+    }
+
+    /// The cell under a position relative to the spawner, `None` above or left of the map.
+    pub fn cell_at(&self, local: Vec2) -> Option<(u32, u32)> {
+        let col = (local.x / self.width).round();
+        let row = (-local.y / self.height).round();
+        (col >= 0.0 && row >= 0.0).then_some((col as u32, row as u32))
+    }
+
+    /// The corners of the area covered by the tiles, relative to the spawner.
+    pub fn world_bounds(&self, tilemap: &TileMap) -> Option<(Vec2, Vec2)> {
+        let (cols, rows) = tilemap.size()?;
+        let half = Vec2::new(0.5 * self.width, 0.5 * self.height);
+        let min = Vec2::new(0.0, -self.height * (rows - 1) as f32) - half;
+        let max = Vec2::new(self.width * (cols - 1) as f32, 0.0) + half;
+        Some((min, max))
+    }
 }
 
 pub enum TileMapSpawnEvent {
@@ -209,7 +241,8 @@ pub enum TileMapSpawnEvent {
     Despawn(Entity),
 }
 
-pub type TileBundle = (Tile, Parent, Transform, GlobalTransform);
+/// A tile with its layer, the z of the layer is in the `Transform`.
+pub type TileBundle = (Tile, Parent, Transform, GlobalTransform, TileLayer);
 
 pub fn sync_tilemap_spawner_system(
     // assets
@@ -220,8 +253,20 @@ pub fn sync_tilemap_spawner_system(
     mut spawn_events: ResMut<Events<TileMapSpawnEvent>>,
     // queries
     spawner_query: Query<(Entity, &TileMapSpawner)>,
-    tile_query: Query<(Entity, &Tile, &Parent)>,
+    added_spawner_query: Query<(Entity, &TileMapSpawner), Added<TileMapSpawner>>,
+    tile_query: Query<(Entity, &Tile, &TileLayer, &Parent)>,
 ) {
+    // spawners of a level entered again find their tilemap loaded, without a Created event
+    for (a_spawner, spawner) in added_spawner_query.iter() {
+        for tilemap in tilemaps.get(&spawner.handle) {
+            for (layer, tile) in tilemap.tiles() {
+                spawn_events.send(TileMapSpawner::spawn(
+                    a_spawner, spawner, tilemap, layer, tile,
+                ));
+            }
+        }
+    }
+
     let events = event_reader.iter(&events).map(MyAssetEvent::from);
 
     for MyAssetEvent(event, handle) in events {
@@ -231,8 +276,10 @@ pub fn sync_tilemap_spawner_system(
 
                 for (a_spawner, spawner) in spawner_query.iter().filter(|(_, t)| t.handle == handle)
                 {
-                    for tile in tilemap.map.iter() {
-                        spawn_events.send(TileMapSpawner::spawn(a_spawner, spawner, tile));
+                    for (layer, tile) in tilemap.tiles() {
+                        spawn_events.send(TileMapSpawner::spawn(
+                            a_spawner, spawner, tilemap, layer, tile,
+                        ));
                     }
                 }
             }
@@ -243,20 +290,25 @@ pub fn sync_tilemap_spawner_system(
                 {
                     let relevant = tile_query
                         .iter()
-                        .filter(|(_, _, Parent(parent))| parent == &a_spawner)
+                        .filter(|(_, _, _, Parent(parent))| parent == &a_spawner)
                         .collect::<Vec<_>>();
-                    let existing_tiles: Vec<_> =
-                        relevant.iter().map(|(_, &tile, _)| tile).collect();
+                    // tiles are compared within their layer, a layer can cover another
+                    let existing_tiles: Vec<_> = relevant
+                        .iter()
+                        .map(|(_, &tile, &layer, _)| (layer, tile))
+                        .collect();
 
-                    for (a_tile, tile, _) in relevant.iter() {
-                        if !tilemap.map.contains(tile) {
+                    for (a_tile, tile, &layer, _) in relevant.iter() {
+                        if !tilemap.contains(layer, tile) {
                             spawn_events.send(TileMapSpawner::despawn(*a_tile));
                         }
                     }
 
-                    for tile in tilemap.map.iter() {
-                        if !existing_tiles.contains(tile) {
-                            spawn_events.send(TileMapSpawner::spawn(a_spawner, spawner, tile));
+                    for (layer, tile) in tilemap.tiles() {
+                        if !existing_tiles.contains(&(layer, *tile)) {
+                            spawn_events.send(TileMapSpawner::spawn(
+                                a_spawner, spawner, tilemap, layer, tile,
+                            ));
                         }
                     }
                 }
@@ -290,7 +342,162 @@ impl From<&AssetEvent<TileMap>> for MyAssetEvent {
 #[derive(Debug, Deserialize, TypeUuid, Default)]
 #[uuid = "39cadc56-aa9c-4543-8640-a018b71b5051"]
 pub struct TileMap {
-    map: Vec<Tile>,
+    layers: Vec<TileMapLayer>,
+}
+
+/// The name of the layer for rows before the first layer header.
+pub const DEFAULT_LAYER: &str = "default";
+
+/// Tiles that share a z, like floor, walls, furniture or spawns.
+#[derive(Debug, Deserialize, Default, Clone, PartialEq)]
+pub struct TileMapLayer {
+    pub name: String,
+    pub z: f32,
+    tiles: Vec<Tile>,
+}
+
+impl TileMapLayer {
+    pub fn new(name: &str, z: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            z,
+            tiles: vec![],
+        }
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = &Tile> {
+        self.tiles.iter()
+    }
+}
+
+/// The layer of a spawned tile, an index into `TileMap::layers`.
+#[derive(Eq, PartialEq, Hash, Debug, Default, Clone, Copy)]
+pub struct TileLayer(pub usize);
+
+impl TileMap {
+    /// Columns and rows up to the last tile of any layer, `None` without tiles.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        let cols = self.tiles().map(|(_, tile)| tile.1 + 1).max()?;
+        let rows = self.tiles().map(|(_, tile)| tile.2 + 1).max()?;
+        Some((cols, rows))
+    }
+
+    /// Reads the text format, one character per cell, rows by line and spaces for no tile.
+    ///
+    /// A line like `[walls 1]` starts a layer with its name and z, rows count from 0 again.
+    /// Without the z the layer is put above the ones before it. Rows before the first
+    /// header go to the `DEFAULT_LAYER` at z 0.
+    pub fn parse(bytes: &[u8]) -> Self {
+        let mut layers: Vec<TileMapLayer> = vec![];
+        let mut row = 0;
+
+        for line in bytes.split(|&c| c == b'\n') {
+            if let Some((name, z)) = Self::parse_header(line) {
+                let z = z.unwrap_or(layers.len() as f32);
+                layers.push(TileMapLayer::new(name, z));
+                row = 0;
+                continue;
+            }
+
+            let mut tiles = line
+                .iter()
+                .enumerate()
+                .filter(|(_, &c)| c != b' ')
+                .map(move |(col, &c)| Tile(c, col as u32, row))
+                .peekable();
+            if tiles.peek().is_some() {
+                if layers.is_empty() {
+                    layers.push(TileMapLayer::new(DEFAULT_LAYER, 0.0));
+                }
+                layers.last_mut().unwrap().tiles.extend(tiles);
+            }
+            row += 1;
+        }
+
+        Self { layers }
+    }
+
+    fn parse_header(line: &[u8]) -> Option<(&str, Option<f32>)> {
+        let line = std::str::from_utf8(line).ok()?.trim_end();
+        let header = line.strip_prefix('[')?.strip_suffix(']')?;
+        let mut words = header.split_whitespace();
+        let name = words.next()?;
+        let z = words.next().and_then(|it| it.parse().ok());
+        Some((name, z))
+    }
+
+    /// Writes the text format read by `parse`, without trailing spaces.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (i, layer) in self.layers.iter().enumerate() {
+            if i > 0 || layer.name != DEFAULT_LAYER || layer.z != 0.0 {
+                text += &format!("[{} {}]\n", layer.name, layer.z);
+            }
+
+            let rows = layer.tiles.iter().map(|tile| tile.2 + 1).max().unwrap_or(0);
+            let mut lines = vec![vec![]; rows as usize];
+            for tile in layer.tiles.iter() {
+                let line = &mut lines[tile.2 as usize];
+                if line.len() <= tile.1 as usize {
+                    line.resize(tile.1 as usize + 1, ' ');
+                }
+                line[tile.1 as usize] = tile.0 as char;
+            }
+            text.extend(lines.iter().flat_map(|line| line.iter().chain(&['\n'])));
+        }
+        text
+    }
+
+    pub fn layers(&self) -> &[TileMapLayer] {
+        &self.layers
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    /// The tiles of all layers, bottom layer first.
+    pub fn tiles(&self) -> impl Iterator<Item = (TileLayer, &Tile)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, layer)| layer.tiles.iter().map(move |tile| (TileLayer(i), tile)))
+    }
+
+    pub fn contains(&self, layer: TileLayer, tile: &Tile) -> bool {
+        self.layers
+            .get(layer.0)
+            .map_or(false, |it| it.tiles.contains(tile))
+    }
+
+    pub fn get(&self, layer: usize, col: u32, row: u32) -> Option<char> {
+        self.layers
+            .get(layer)?
+            .tiles
+            .iter()
+            .find(|tile| tile.1 == col && tile.2 == row)
+            .map(|tile| tile.0 as char)
+    }
+
+    /// Puts `tile` into a cell of a layer, or clears it with `None`, and returns what was there.
+    /// Layers up to `layer` are added when missing.
+    pub fn set(&mut self, layer: usize, col: u32, row: u32, tile: Option<char>) -> Option<char> {
+        let before = self.get(layer, col, row);
+        while self.layers.len() <= layer {
+            let name = match self.layers.len() {
+                0 => DEFAULT_LAYER.to_string(),
+                n => format!("layer{}", n),
+            };
+            let z = self.layers.len() as f32;
+            self.layers.push(TileMapLayer::new(&name, z));
+        }
+        let tiles = &mut self.layers[layer].tiles;
+        tiles.retain(|it| it.1 != col || it.2 != row);
+        if let Some(c) = tile {
+            tiles.push(Tile(c as u8, col, row));
+        }
+        before
+    }
 }
 
 #[derive(Default)]
@@ -304,23 +511,7 @@ impl AssetLoader for TileMapLoader {
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
             println!("TileMapLoader reload");
-            let mut map = vec![];
-            let mut col = 0;
-            let mut row = 0;
-
-            for &c in bytes.iter() {
-                if c == '\n' as u8 {
-                    col = 0;
-                    row += 1;
-                } else {
-                    if c != ' ' as u8 {
-                        map.push(Tile(c, col, row));
-                    }
-                    col += 1;
-                }
-            }
-
-            load_context.set_default_asset(LoadedAsset::new(TileMap { map }));
+            load_context.set_default_asset(LoadedAsset::new(TileMap::parse(bytes)));
             Ok(())
         })
     }
@@ -8,60 +8,67 @@
     I learned that in order to use rapier physics I need to manually put
     the entity int othe physics user_data to look it up again in the
     event handler.
+
+    thrown stones fly up and over the trees, with a shadow on the ground
 */
 
 use bevy::{ecs::DynamicBundle, prelude::*};
 use bevy_rapier2d::{
-    na::{Isometry2, Vector2},
+    na::Vector2,
     physics::*,
     rapier::{dynamics::*, geometry::*},
     render::*,
 };
 use level1::{
-    CanBeItemBasics, CanItemBasics, Carried, ContactType, ControlRandomMovement, Kinematics,
-    MovementAbility, SoundOnContact, SoundType, Stone, Thrown,
+    CanBeItemBasics, CanItemBasics, ContactType, ControlRandomMovement, Drag, MovementAbility,
+    SoundOnContact, SoundType, Stone, Velocity,
 };
-use level2::{TileBundle, TileMap, TileMapLoader, TileMapSpawnEvent, TileMapSpawner};
+use level2::{TileBundle, TileMap, TileMapSpawnEvent, TileMapSpawner, TILE_PALETTE};
 
 use crate::{
-    bevy_rapier_utils::IntoVector2,
-    bitpack::{Bitpack, BitpackPlugin},
+    bitpack::Bitpack,
     bundle_utils::{sprite_bundle, static_tile_physics_bundle},
+    collision_layer::CollisionLayer,
     commands_ext::CommandsExt,
     levels::level1::{self, RandomVec},
-    levels::level2,
+    levels::{editor::TileMapPalette, level2, AppState, LevelBuilder, LevelInfo},
+    replay::GameTime,
+    rng::GameRng,
+    save::PendingLoad,
+    systems::{
+        camera::CameraEffects,
+        contact_sound::{SoundContact, FULL_VOLUME_SPEED},
+        height::{Height, HeightCollider},
+    },
 };
 
 pub fn app() -> AppBuilder {
-    let mut app = App::build();
-    app.add_plugins(DefaultPlugins)
-        .add_plugin(BitpackPlugin)
-        .add_plugin(Level3Plugin);
-    app
+    super::app(Some("level3"))
 }
 
+/// Needs rapier, `MovementPlugin::with_rapier`, `HeightPlugin` and `ContactSoundPlugin`,
+/// see `levels::app`.
 pub struct Level3Plugin;
 
 impl Plugin for Level3Plugin {
     fn build(&self, app: &mut AppBuilder) {
-        app /**/
-            .add_plugin(RapierPhysicsPlugin)
-            //
-            .add_startup_system(setup_physics.system())
-            .add_startup_system(level1::add_camera.system())
-            .add_startup_system(add_tilemap.system())
-            //
-            .add_system(spawn_from_tilemap.system())
-            .add_system(control_random_movement_system.system())
-            .add_system(level1::control_random_item_basics_system.system())
-            .add_system(carry_system.system())
-            .add_system(throw_system.system())
-            // TODO this could be a tilemap plugin
-            .add_system(level2::sync_tilemap_spawner_system.system())
-            .add_asset::<TileMap>()
-            .init_asset_loader::<TileMapLoader>()
-            .add_event::<TileMapSpawnEvent>()
-            /* end */;
+        LevelBuilder::new(
+            app,
+            LevelInfo {
+                state: AppState::Level3,
+                name: "level3",
+                title: "throwing stones with physics",
+            },
+        )
+        .reset_resource_on_exit::<PendingLoad>()
+        .reset_resource_on_exit::<RapierConfiguration>()
+        .on_enter(setup_physics.system())
+        .on_enter(level1::add_camera.system())
+        .on_enter(add_tilemap.system())
+        .add_system(spawn_from_tilemap.system())
+        .add_system(control_random_movement_system.system())
+        .add_system(level1::control_random_item_basics_system.system())
+        .add_system(stone_hit_feedback_system.system());
     }
 }
 
@@ -69,46 +76,29 @@ fn setup_physics(mut config: ResMut<RapierConfiguration>) {
     config.gravity = Vector2::new(0.0, 0.0);
 }
 
-fn throw_system(
-    commands: &mut Commands,
-    mut bodies: ResMut<RigidBodySet>,
-    mut query: Query<(Entity, &Thrown, &RigidBodyHandleComponent)>,
-) {
-    for (entity, thrown, body_handle) in query.iter_mut() {
-        if let Some(body) = bodies.get_mut(body_handle.handle()) {
-            body.apply_impulse((thrown.vel * 50.0).into_vector2(), true);
-            body.set_angvel(0.0, false);
-        }
-        commands.remove_one::<Thrown>(entity);
-    }
-}
-
-fn carry_system(
-    commands: &mut Commands,
-    mut bodies: ResMut<RigidBodySet>,
-    mut query: Query<(Entity, &Carried, &RigidBodyHandleComponent)>,
-    transform: Query<&Transform, Without<Carried>>,
+/// Stones hitting walls shake the camera, harder the faster they were.
+fn stone_hit_feedback_system(
+    mut effects: ResMut<CameraEffects>,
+    mut reader: Local<EventReader<SoundContact>>,
+    events: Res<Events<SoundContact>>,
+    stones: Query<&Stone>,
 ) {
-    for (item, carried, handle) in query.iter_mut() {
-        if let Ok(owner_trans) = transform.get(carried.owner) {
-            if let Some(body) = bodies.get_mut(handle.handle()) {
-                let pos = owner_trans.translation + carried.offset.translation;
-                body.set_position(Isometry2::new(pos.into_vector2(), 0.0), false);
-                body.set_angvel(0.0, false);
-                body.set_linvel(Vector2::new(0.0, 0.0), false);
-            }
-        } else {
-            commands.remove_one::<Carried>(item);
+    for contact in reader.iter(&events) {
+        if contact.contact == ContactType::Wall && stones.get(contact.entity).is_ok() {
+            let strength = (contact.speed / FULL_VOLUME_SPEED).min(1.0);
+            effects.add_trauma(0.5 * strength);
+            effects.flash(Color::rgba(1.0, 1.0, 1.0, 0.3 * strength), 0.15);
         }
     }
 }
 
 pub fn control_random_movement_system(
-    time: Res<Time>,
-    mut bodies: ResMut<RigidBodySet>,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
     mut query: Query<(
         Mut<ControlRandomMovement>,
-        Mut<RigidBodyHandleComponent>,
+        Mut<Velocity>,
+        &Transform,
         &CanItemBasics,
         &MovementAbility,
     )>,
@@ -121,22 +111,18 @@ pub fn control_random_movement_system(
     }
 
     let dt = time.delta_seconds();
-    let mut rng = rand::thread_rng();
-    for (mut control, body_handle, can, movement) in query.iter_mut() {
+    let rng = rng.stream("random_movement");
+    for (mut control, mut velocity, trans, can, movement) in query.iter_mut() {
         if control.timer.tick(dt).finished() {
-            if let Some(body) = bodies.get_mut(body_handle.handle()) {
-                let top_speed = movement.top_speed;
-
-                if can.picked_up.is_some() {
-                    let vel = rng.random_vec2d() * top_speed * 0.8;
-                    body.set_linvel(vel.into_vector2(), true);
-                } else {
-                    let trans = body.position().translation;
-                    let dir = (mid - Vec3::new(trans.x, trans.y, 0.0)).normalize();
-                    let vel = dir.lerp(rng.random_vec2d(), 0.4) * top_speed * 0.8;
-                    body.set_linvel(vel.into_vector2(), true);
-                }
-            }
+            let top_speed = movement.top_speed;
+
+            let vel = if can.picked_up.is_some() {
+                rng.random_vec2d() * top_speed * 0.8
+            } else {
+                let dir = (mid - trans.translation).truncate().extend(0.0).normalize();
+                dir.lerp(rng.random_vec2d(), 0.4) * top_speed * 0.8
+            };
+            velocity.0 = vel.truncate();
         }
     }
 }
@@ -194,7 +180,7 @@ fn mage_physics_bundle(entity: Entity, transform: Transform) -> impl DynamicBund
             .translation(transform.translation.x, transform.translation.y)
             .lock_rotations()
             .user_data(entity.to_bits() as u128),
-        ColliderBuilder::ball(4.0).collision_groups(InteractionGroups::new(0x0002, 0x0002)),
+        ColliderBuilder::ball(4.0).collision_groups(CollisionLayer::NPC.groups()),
         RapierRenderColor(1.0, 0.0, 0.0),
     )
 }
@@ -203,9 +189,10 @@ fn stone_physics_bundle(entity: Entity, transform: Transform) -> impl DynamicBun
     (
         RigidBodyBuilder::new_dynamic()
             .translation(transform.translation.x, transform.translation.y)
-            .linear_damping(0.97)
             .user_data(entity.to_bits() as u128),
-        ColliderBuilder::ball(3.0).collision_groups(InteractionGroups::new(0x0001, 0x0001)),
+        HeightCollider::new(
+            ColliderBuilder::ball(3.0).collision_groups(CollisionLayer::Item.groups()),
+        ),
         RapierRenderColor(1.0, 0.0, 0.0),
     )
 }
@@ -221,23 +208,21 @@ fn stone_bundle() -> impl DynamicBundle {
             drop: true,
             throw: true,
         },
-        Kinematics {
-            vel: Vec3::zero(),
-            drag: 0.97,
-        },
+        Velocity::default(),
+        Drag(0.97),
+        Height::default(),
         SoundOnContact::new(vec![(Ground, Clonk), (Wall, Bling)]),
     )
 }
 
 fn add_tilemap(asset_server: Res<AssetServer>, commands: &mut Commands) {
-    asset_server.watch_for_changes().unwrap();
-
     let tilemap_handle: Handle<TileMap> = asset_server.load("level3.tilemap");
 
     let tilemap_bundle = (
         Transform::from_translation(Vec3::new(-64.0, 64.0, 0.0)),
         GlobalTransform::default(),
         TileMapSpawner::new(tilemap_handle),
+        TileMapPalette(TILE_PALETTE.to_vec()),
         Children::default(),
     );
 
@@ -9,56 +9,73 @@
 
 use std::fmt::Debug;
 
-use bevy::{
-    ecs::DynamicBundle, input::system::exit_on_esc_system, math::Vec3Swizzles, prelude::*,
-    render::camera::Camera,
-};
+use bevy::{ecs::DynamicBundle, prelude::*};
 use level2::TileMapSpawner;
 
 use crate::{
     bevy_rapier_utils::*,
-    bitpack::{Bitpack, BitpackPlugin},
+    bitpack::Bitpack,
     bundle_utils::sprite_bundle,
+    collision_layer::CollisionLayer,
     commands_ext::CommandsExt,
     components::*,
-    entities::player::*,
+    entities::{oven_update, player::*, OvenState, OvenTransfer},
+    input::*,
     interactions::*,
-    levels::level2::{self, TileBundle, TileMap, TileMapLoader, TileMapSpawnEvent},
-    rapier_debug_render::rapier_debug_render,
+    levels::{
+        editor::TileMapPalette,
+        level2::{self, TileBundle, TileMap, TileMapSpawnEvent},
+        AppState, LevelBuilder, LevelInfo,
+    },
+    rng::{GameRng, GameRngStream},
+    save::{PendingLoad, Saved},
+    systems::{
+        camera::{CameraController, CameraEffects},
+        inventory::{Inventory, Items},
+    },
     utils::*,
 };
 
 pub fn app() -> AppBuilder {
-    let mut app = App::build();
-    app.add_plugins(DefaultPlugins)
-        .add_plugin(BitpackPlugin)
-        .add_plugin(RapierPhysicsPlugin)
-        //
-        .add_startup_system(setup.system())
-        //
-        .add_system(level2::sync_tilemap_spawner_system.system())
-        .add_asset::<TileMap>()
-        .init_asset_loader::<TileMapLoader>()
-        .add_event::<TileMapSpawnEvent>()
-        //
-        .add_event::<PlayerEvent>()
-        .add_event::<GameInteraction>()
-        //
-        .add_system_to_stage(stage::PRE_UPDATE, tilemap_spawn_events_handler.system())
+    super::app(Some("level4"))
+}
+
+/// Needs rapier, input, replay, interactions, the journal and proximity plugins
+/// and the `Items` resource, see `levels::app`.
+pub struct Level4Plugin;
+
+impl Plugin for Level4Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<PlayerEvent>();
+
+        LevelBuilder::new(
+            app,
+            LevelInfo {
+                state: AppState::Level4,
+                name: "level4",
+                title: "local co-op in a house",
+            },
+        )
+        .reset_resource_on_exit::<PendingLoad>()
+        .reset_resource_on_exit::<RapierConfiguration>()
+        .on_enter(setup.system())
+        .add_pre_update_system(tilemap_spawn_events_handler.system())
         .add_system(spawn_dress.system())
         .add_system(spawn_physics.system())
-        .add_system(camera_tracks_player.system())
         .add_system(player_input.system())
-        .add_system(manage_proximity_set.system())
+        .add_system(player_facing_system.system())
+        .add_system(player_forward_sensor_system.system())
+        .add_system(player_facing_flip_system.system())
         .add_system(player_spawn_system.system())
+        .add_system(player_join_system.system())
+        .add_system(player_hud_system.system())
         .add_system(player_handle_input_events.system())
-        .add_system(rapier_debug_render.system())
-        //
-        .add_system(interactions_system.system())
+        .add_system(player_fish_system.system())
+        .add_system(oven_station_system.system())
+        .add_system(oven_update.system())
         // .add_system(print_positions.system())
-        .add_system(exit_on_esc_system.system());
-
-    app
+        .add_system(push_feedback_system.system());
+    }
 }
 
 fn setup(
@@ -70,52 +87,33 @@ fn setup(
     config.gravity = Vector2::new(0.0, 0.0);
     clear_color.0 = Color::rgb(0.278, 0.176, 0.235);
 
-    asset_server.watch_for_changes().unwrap();
-
     let tilemap_handle: Handle<TileMap> = asset_server.load("level4.tilemap");
 
     let tilemap_bundle = (
         Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
         GlobalTransform::default(),
         TileMapSpawner::new(tilemap_handle),
+        TileMapPalette(TILE_MARKER_MAP.iter().map(|(c, _)| *c).collect()),
         Children::default(),
     );
 
     commands.spawn(tilemap_bundle);
 
-    let camera = commands.entity({
-        let mut bundle = Camera2dBundle::default();
-        bundle.transform.scale = Vec3::new(0.5, 0.5, 1.0);
-        bundle
-    });
-
-    commands.insert_resource(PlayerCamera(camera));
-
-    let font = asset_server.load("FiraSans-Bold.ttf");
+    commands.spawn(
+        CameraController::new(CAMERA_MIN_SCALE)
+            .fit_targets(CAMERA_MARGIN)
+            .pixel_perfect(1.0)
+            .bundle(),
+    );
 
     commands.spawn(CameraUiBundle::default());
-
-    let text = commands.entity(TextBundle {
-        text: Text {
-            value: "Press E".into(),
-            style: TextStyle {
-                font_size: 32.0,
-                ..Default::default()
-            },
-            font,
-        },
-        style: Style {
-            align_self: AlignSelf::FlexEnd,
-            ..Default::default()
-        },
-        ..Default::default()
-    });
-
-    commands.insert_resource(PlayerSensingUi(text));
 }
 
-struct PlayerCamera(Entity);
-struct PlayerSensingUi(Entity);
+/// The HUD text of one player, listed in the corner by player id.
+/// It is despawned once its player is gone.
+struct PlayerHud {
+    player: Entity,
+}
 
 const TILE_MARKER_MAP: &[(char, Marker)] = {
     use Marker::*;
@@ -137,21 +135,32 @@ const TILE_MARKER_MAP: &[(char, Marker)] = {
 };
 
 trait Level4Commands {
-    fn spawn_tile(&mut self, tile: TileBundle);
-    fn spawn_marker(&mut self, marker: Marker, bundle: impl DynamicBundle + Send + Sync + 'static);
+    fn spawn_tile(&mut self, tile: TileBundle, rng: &mut GameRngStream);
+    fn spawn_marker(
+        &mut self,
+        marker: Marker,
+        bundle: impl DynamicBundle + Send + Sync + 'static,
+        rng: &mut GameRngStream,
+    );
 }
 
 impl Level4Commands for Commands {
-    fn spawn_tile(&mut self, tile: TileBundle) {
+    fn spawn_tile(&mut self, tile: TileBundle, rng: &mut GameRngStream) {
         for (_, marker) in TILE_MARKER_MAP
             .iter()
             .filter(|(char, _)| *char == tile.0 .0 as char)
         {
-            self.spawn_marker(*marker, (tile.0, tile.2, GlobalTransform::default()));
+            // `Parent` and `TileLayer` let `sync_tilemap_spawner_system` diff the tile on reload
+            self.spawn_marker(*marker, tile, rng);
         }
     }
 
-    fn spawn_marker(&mut self, marker: Marker, bundle: impl DynamicBundle + Send + Sync + 'static) {
+    fn spawn_marker(
+        &mut self,
+        marker: Marker,
+        bundle: impl DynamicBundle + Send + Sync + 'static,
+        rng: &mut GameRngStream,
+    ) {
         let _entity = self.entity(bundle);
         self.with(marker);
 
@@ -164,6 +173,7 @@ impl Level4Commands for Commands {
                 .with(Dress::Bitpack(826, Color::GRAY))
                 .with(Physics::SolidTile(desc)),
             Marker::Chair => self
+                .with(Saved)
                 .with(Dress::Bitpack(385, Color::SALMON))
                 .with(Physics::DynamicBall(desc)),
             Marker::Table => self
@@ -172,7 +182,14 @@ impl Level4Commands for Commands {
             Marker::Window => self
                 .with(Dress::Bitpack(827, Color::GRAY))
                 .with(Physics::SolidTile(desc)),
-            Marker::Door => self.with(Dress::Bitpack(9 * 48 + 6, Color::GRAY)),
+            Marker::Door => self
+                .with(Saved)
+                .with(Dress::Bitpack(9 * 48 + 6, Color::GRAY))
+                .with(Openable {
+                    open: false,
+                    half_extents: desc.size * 0.5,
+                })
+                .with(Physics::SolidTile(desc)),
             Marker::Bookshelf => self
                 .with(Dress::Bitpack(7 * 48 + 3, Color::SALMON))
                 .with(Physics::SolidTile(desc)),
@@ -180,6 +197,13 @@ impl Level4Commands for Commands {
                 .with(Dress::Bitpack(8 * 48, Color::SALMON))
                 .with(Physics::SolidTile(desc)),
             Marker::Oven => self
+                .with(Saved)
+                .with(OvenState {
+                    baking_timer: Timer::from_seconds(OVEN_BAKING_SECONDS, false),
+                    item: None,
+                    baked_item: None,
+                    on_fire: false,
+                })
                 .with(Dress::Bitpack(8 * 48 + 8, Color::SALMON))
                 .with(Physics::SolidTile(desc)),
             Marker::Bed => self
@@ -188,7 +212,7 @@ impl Level4Commands for Commands {
             Marker::Dirt => self.with(Dress::Bitpack(3, Color::SALMON)),
             Marker::RandomTree => self
                 .with(Dress::Bitpack(
-                    [48, 49, 50, 51, 52, 53, 99, 100].random(),
+                    [48, 49, 50, 51, 52, 53, 99, 100].random(rng),
                     Color::rgb(0.22, 0.851, 0.451),
                 ))
                 .with(Physics::SolidTile(desc)),
@@ -219,13 +243,14 @@ fn spawn_dress(
     for (entity, &dress, trans, _gtrans) in query.iter() {
         let atlas = bitpack.atlas_handle.clone();
 
-        let Vec3 { x, y, .. } = trans.translation;
+        // the z of the tilemap layer stacks floor, furniture and the rest
+        let Vec3 { x, y, z } = trans.translation;
         let trans = Transform {
-            translation: Vec3 { x, y, z: 50.0 },
+            translation: Vec3 { x, y, z: 50.0 + z },
             rotation: trans.rotation,
             scale: trans.scale,
         };
-        let gtrans = GlobalTransform::from_translation(Vec3 { x, y, z: 50.0 });
+        let gtrans = GlobalTransform::from_translation(trans.translation);
 
         if std::env::var("spawn_dress").is_ok() {
             eprintln!("spawn_dress {:?} {:?}", trans.translation, dress);
@@ -259,7 +284,8 @@ fn spawn_physics(
                     .user_data(user_data),))
                 .with_child((
                     ColliderBuilder::cuboid(desc.size.x * 0.5, desc.size.y * 0.5)
-                        .user_data(user_data), // .collision_groups(InteractionGroups::new(0x0002, 0xffff)),
+                        .user_data(user_data)
+                        .collision_groups(CollisionLayer::Wall.groups()),
                 )),
             Physics::DynamicBall(desc) => commands
                 .with_bundle((RigidBodyBuilder::new_dynamic()
@@ -267,21 +293,24 @@ fn spawn_physics(
                     .user_data(user_data)
                     .linear_damping(8.0)
                     .angular_damping(4.0),))
-                .with_child((
-                    ColliderBuilder::ball(desc.size.x * 0.49).user_data(user_data), // .collision_groups(InteractionGroups::new(0x0002, 0xffff)),
-                )),
+                .with_child((ColliderBuilder::ball(desc.size.x * 0.49)
+                    .user_data(user_data)
+                    .collision_groups(CollisionLayer::Item.groups()),)),
         };
     }
 }
 
 fn tilemap_spawn_events_handler(
     commands: &mut Commands,
+    mut rng: ResMut<GameRng>,
     mut event_reader: Local<EventReader<TileMapSpawnEvent>>,
     events: Res<Events<TileMapSpawnEvent>>,
 ) {
+    let rng = rng.stream("level4_tiles");
+
     for event in event_reader.iter(&events) {
         match event {
-            TileMapSpawnEvent::Spawn(bundle) => commands.spawn_tile(*bundle),
+            TileMapSpawnEvent::Spawn(bundle) => commands.spawn_tile(*bundle, rng),
             TileMapSpawnEvent::Despawn(a_tile) => {
                 commands.despawn_recursive(*a_tile);
             }
@@ -289,104 +318,146 @@ fn tilemap_spawn_events_handler(
     }
 }
 
-fn camera_tracks_player(
-    camera: Res<PlayerCamera>,
-    query: Query<&GlobalTransform, With<Player>>,
-    mut transform: Query<Mut<Transform>, With<Camera>>,
-) {
-    if let Ok(mut camera_trans) = transform.get_mut(camera.0) {
-        let mut mid = camera_trans.translation.xy();
-
-        for trans in query.iter() {
-            mid = 0.5 * (mid + trans.translation.xy());
-        }
+/// The zoom of the shared camera with one player, it zooms out until all players fit.
+const CAMERA_MIN_SCALE: f32 = 0.5;
+/// World units kept around the players when fitting them.
+const CAMERA_MARGIN: f32 = 64.0;
 
-        if camera_trans.translation.xy() != mid {
-            let vec = camera_trans.translation.xy().lerp(mid, 0.125).round();
-            camera_trans.translation.x = vec.x;
-            camera_trans.translation.y = vec.y;
-        }
+/// Shakes and punches the camera when things are pushed away.
+fn push_feedback_system(
+    mut effects: ResMut<CameraEffects>,
+    mut outcome_reader: Local<EventReader<InteractionEvent>>,
+    outcomes: Res<Events<InteractionEvent>>,
+) {
+    let pushes = outcome_reader
+        .iter(&outcomes)
+        .filter(|it| matches!(it.kind, InteractionKind::Push { .. }))
+        .count();
+
+    if pushes > 0 {
+        effects.add_trauma(0.4);
+        effects.zoom_punch(0.05, 0.2);
     }
 }
 
 fn player_input(
-    keys: Res<Input<KeyCode>>,
     mut events: ResMut<Events<PlayerEvent>>,
     //
     mut bodies: ResMut<RigidBodySet>,
-    query: Query<&RigidBodyHandleComponent, With<Player>>,
+    query: Query<
+        (
+            Entity,
+            &ActionState,
+            &RigidBodyHandleComponent,
+            Option<&Sleeping>,
+        ),
+        With<Player>,
+    >,
 ) {
-    let mut cursor = Vec3::default();
-
-    if keys.pressed(KeyCode::W) {
-        cursor.y += 1.0;
-    }
-    if keys.pressed(KeyCode::A) {
-        cursor.x -= 1.0;
-    }
-    if keys.pressed(KeyCode::S) {
-        cursor.y -= 1.0;
-    }
-    if keys.pressed(KeyCode::D) {
-        cursor.x += 1.0;
-    }
+    for (entity, actions, body, sleeping) in query.iter() {
+        if sleeping.is_some() {
+            if let Some(body) = bodies.get_mut(body.handle()) {
+                body.set_linvel(Vec2::zero().into_vector2(), false);
+            }
+            continue;
+        }
 
-    let cursor = if cursor != Vec3::zero() {
-        (140.0 * cursor.normalize()).into_vector2()
-    } else {
-        Vector2::new(0.0, 0.0)
-    };
+        let cursor = (140.0 * actions.movement()).into_vector2();
 
-    for body in query.iter() {
         if let Some(body) = bodies.get_mut(body.handle()) {
             body.set_linvel(cursor, true);
         }
-    }
 
-    if keys.just_pressed(KeyCode::E) {
-        events.send(PlayerEvent::Interact);
+        if actions.just_pressed(InputAction::Interact) {
+            events.send(PlayerEvent::Interact(entity));
+        }
+        if actions.just_pressed(InputAction::Observe) {
+            events.send(PlayerEvent::Observe(entity));
+        }
     }
-    if keys.just_pressed(KeyCode::F) {
-        events.send(PlayerEvent::Observe);
+}
+
+const OVEN_BAKING_SECONDS: f32 = 3.0;
+
+/// Every joining player brings a fish to bake.
+fn player_fish_system(items: Res<Items>, mut query: Query<Mut<Inventory>, Added<PlayerId>>) {
+    for mut inventory in query.iter_mut() {
+        inventory.put(&items.fish);
     }
 }
 
-fn manage_proximity_set(
-    rapier_events: Res<EventQueue>,
-    colliders: Res<ColliderSet>,
-    bodies: Res<RigidBodySet>,
-    mut proximity_q: Query<Mut<ProximitySet>>,
+/// Ovens trade with the last item of the player's inventory, see `OvenState::interact`.
+fn oven_station_system(
+    items: Res<Items>,
+    mut reader: Local<EventReader<InteractionEvent>>,
+    events: Res<Events<InteractionEvent>>,
+    mut oven_query: Query<Mut<OvenState>>,
+    mut inventory_query: Query<Mut<Inventory>>,
 ) {
-    let get_parent_entity = |h| colliders.get_parent(h).and_then(|p| bodies.get_entity(p));
-
-    while let Ok(ProximityEvent {
-        collider1: c1,
-        collider2: c2,
-        new_status,
-        ..
-    }) = rapier_events.proximity_events.pop()
-    {
-        let e1 = colliders.get_entity(c1);
-        let e2 = colliders.get_entity(c2);
-
-        match new_status {
-            Proximity::Intersecting => {
-                for mut proximity in e1.and_then(|e| proximity_q.get_mut(e).ok()) {
-                    get_parent_entity(c2).map(|e| proximity.insert(e));
-                }
-                for mut proximity in e2.and_then(|e| proximity_q.get_mut(e).ok()) {
-                    get_parent_entity(c1).map(|e| proximity.insert(e));
-                }
+    for event in reader.iter(&events) {
+        if !matches!(event.kind, InteractionKind::Station { .. }) {
+            continue;
+        }
+
+        if let (Ok(mut oven), Ok(mut inventory)) = (
+            oven_query.get_mut(event.which),
+            inventory_query.get_mut(event.actor),
+        ) {
+            let held = inventory.items.last().cloned();
+            match oven.interact(&items, held.as_ref()) {
+                Some(OvenTransfer::Put(item)) => inventory.take(&item),
+                Some(OvenTransfer::Take(item)) => inventory.put(&item),
+                None => {}
             }
-            Proximity::WithinMargin => {}
-            Proximity::Disjoint => {
-                for mut proximity in e1.and_then(|e| proximity_q.get_mut(e).ok()) {
-                    get_parent_entity(c2).map(|e| proximity.remove(&e));
-                }
-                for mut proximity in e2.and_then(|e| proximity_q.get_mut(e).ok()) {
-                    get_parent_entity(c1).map(|e| proximity.remove(&e));
-                }
+        }
+    }
+}
+
+/// Spawns a HUD line for every joining player.
+fn player_hud_system(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    added: Query<(Entity, &PlayerId), Added<PlayerId>>,
+    players: Query<(&PlayerId, &Inventory)>,
+    mut huds: Query<(Entity, &PlayerHud, Mut<Text>)>,
+) {
+    for (entity, id) in added.iter() {
+        commands.spawn(TextBundle {
+            text: Text {
+                value: String::new(),
+                style: TextStyle {
+                    font_size: 24.0,
+                    color: id.color(),
+                    ..Default::default()
+                },
+                font: asset_server.load("FiraSans-Bold.ttf"),
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(8.0),
+                    bottom: Val::Px(8.0 + 28.0 * id.0 as f32),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        commands.with(PlayerHud { player: entity });
+    }
+
+    for (hud, PlayerHud { player }, mut text) in huds.iter_mut() {
+        let (id, inventory) = match players.get(*player) {
+            Ok(it) => it,
+            Err(_) => {
+                commands.despawn_recursive(hud);
+                continue;
             }
+        };
+        let value = format!("P{}: {} items", id.0 + 1, inventory.items.len());
+
+        if text.value != value {
+            text.value = value;
         }
     }
 }
@@ -1,5 +1,120 @@
+pub mod editor;
 pub mod level1;
 pub mod level2;
 pub mod level3;
 pub mod level4;
 pub mod level5;
+mod registry;
+
+use bevy::prelude::*;
+use bevy_rapier2d::physics::RapierPhysicsPlugin;
+
+use crate::{
+    assets::TexAtlases,
+    bitpack::BitpackPlugin,
+    entities::{InteractionPromptPlugin, JournalPlugin},
+    input::InputMapPlugin,
+    interactions::InteractionsPlugin,
+    rapier_debug_render::RapierDebugRenderPlugin,
+    replay::ReplayPlugin,
+    rng::GameRng,
+    save::SavePlugin,
+    systems::{
+        camera::{tilemap_bounds_system, CameraPlugin},
+        contact_sound::ContactSoundPlugin,
+        height::HeightPlugin,
+        inventory::{Item, Items},
+        movement::MovementPlugin,
+        proximity::ProximityPlugin,
+        texture_atlas_utils::{texture_atlas_grid, TextureAtlasUtilsPlugin},
+    },
+};
+use editor::TileMapEditorPlugin;
+use level2::{TileMap, TileMapLoader, TileMapSpawnEvent};
+
+pub use registry::*;
+
+/// All levels in one app, starting with the level called `start` or the level select menu.
+pub fn app(start: Option<&str>) -> AppBuilder {
+    let mut app = App::build();
+    app.add_plugins(DefaultPlugins)
+        .add_plugin(BitpackPlugin)
+        .add_plugin(RapierPhysicsPlugin)
+        .add_plugin(RapierDebugRenderPlugin)
+        .add_plugin(InputMapPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(InteractionsPlugin)
+        .add_plugin(InteractionPromptPlugin)
+        .add_plugin(JournalPlugin)
+        .add_plugin(ProximityPlugin)
+        .add_plugin(MovementPlugin::with_rapier())
+        .add_plugin(HeightPlugin)
+        .add_plugin(ContactSoundPlugin::default())
+        .add_plugin(CameraPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(TextureAtlasUtilsPlugin)
+        .init_resource::<GameRng>()
+        .add_startup_system(watch_assets.system())
+        //
+        .add_asset::<Item>()
+        .add_startup_system(setup_items.system())
+        .add_asset::<TileMap>()
+        .init_asset_loader::<TileMapLoader>()
+        .add_event::<TileMapSpawnEvent>()
+        .add_system(level2::sync_tilemap_spawner_system.system())
+        .add_system(tilemap_bounds_system.system())
+        .add_plugin(TileMapEditorPlugin)
+        //
+        .add_plugin(LevelsPlugin)
+        .add_plugin(level1::Level1Plugin)
+        .add_plugin(level2::Level2Plugin)
+        .add_plugin(level3::Level3Plugin)
+        .add_plugin(level4::Level4Plugin);
+
+    let state = start.and_then(|name| {
+        let registry = app.resources().get::<LevelRegistry>()?;
+        let level = registry.find(name);
+        if level.is_none() {
+            bevy::log::warn!("unknown level {}", name);
+        }
+        level.map(|it| it.state)
+    });
+    if let Some(state) = state {
+        app.resources_mut().insert(State::new(state));
+    }
+
+    app
+}
+
+/// Tilemaps and data files reload when they change, for every level.
+fn watch_assets(asset_server: Res<AssetServer>) {
+    asset_server.watch_for_changes().unwrap();
+}
+
+/// Items are shared by all levels, their sprites live in the oven atlas.
+fn setup_items(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut item_assets: ResMut<Assets<Item>>,
+) {
+    let mut atlas = |path: &str| {
+        let texture = asset_server.load(path);
+        texture_atlas_grid(
+            texture,
+            Vec2::new(8.0, 8.0),
+            Vec2::zero(),
+            &mut atlases,
+            commands,
+        )
+    };
+
+    let atlases = TexAtlases {
+        human_atlas: atlas("human.png"),
+        oven_atlas: atlas("oven.png"),
+    };
+    let items = Items::new(&mut item_assets, &atlases);
+
+    commands.insert_resource(atlases);
+    commands.insert_resource(items);
+}
@@ -0,0 +1,318 @@
+use bevy::{app::AppExit, ecs::System, prelude::*, utils::HashSet};
+
+use crate::input::{ActionState, InputAction};
+
+/// Runs one level at a time as a state of the app, with a level select menu in between.
+/// Levels register themselves through `LevelBuilder`, their systems only run while
+/// the level is active and everything spawned in it is despawned when it is left.
+/// The `Menu` action goes back to the menu, or quits from the menu. The menu starts
+/// a level with its `SelectSlot` action or with `Interact` after `MoveUp` and `MoveDown`.
+/// Needs the `InputMapPlugin`.
+pub struct LevelsPlugin;
+
+/// Runs the enter and exit hooks and the pre update systems of the current state.
+pub const LEVEL_PRE_UPDATE_STAGE: &'static str = "level_pre_update";
+/// Runs the update systems of the current state, right before `stage::UPDATE`
+/// so shared systems see what the level spawned in the same frame.
+pub const LEVEL_UPDATE_STAGE: &'static str = "level_update";
+
+impl Plugin for LevelsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.resources_mut().insert(State::new(AppState::Menu));
+        app.init_resource::<LevelRegistry>()
+            .init_resource::<LevelEntities>()
+            .init_resource::<CurrentLevel>()
+            .add_stage_before(
+                stage::UPDATE,
+                LEVEL_PRE_UPDATE_STAGE,
+                StateStage::<AppState>::default(),
+            )
+            .add_stage_before(
+                stage::UPDATE,
+                LEVEL_UPDATE_STAGE,
+                StateStage::<AppState>::default(),
+            )
+            .on_state_enter(
+                LEVEL_PRE_UPDATE_STAGE,
+                AppState::Menu,
+                level_snapshot_system.system(),
+            )
+            .on_state_enter(LEVEL_PRE_UPDATE_STAGE, AppState::Menu, menu_setup.system())
+            .on_state_update(
+                LEVEL_UPDATE_STAGE,
+                AppState::Menu,
+                menu_select_system.system(),
+            )
+            .on_state_exit(
+                LEVEL_PRE_UPDATE_STAGE,
+                AppState::Menu,
+                level_cleanup_system.system(),
+            )
+            .add_system(escape_system.system());
+    }
+}
+
+#[test]
+fn registry_finds_levels() {
+    let mut registry = LevelRegistry::default();
+    registry.register(LevelInfo {
+        state: AppState::Level1,
+        name: "level1",
+        title: "stones and mages",
+    });
+    registry.register(LevelInfo {
+        state: AppState::Level3,
+        name: "level3",
+        title: "physics",
+    });
+
+    assert_eq!(
+        registry.find("level3").map(|it| it.state),
+        Some(AppState::Level3)
+    );
+    assert!(registry.find("level2").is_none());
+    assert_eq!(
+        registry.levels().map(|it| it.name).collect::<Vec<_>>(),
+        vec!["level1", "level3"]
+    );
+}
+
+/// One state per level, bevy keeps the systems of a state by its variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    Menu,
+    Level1,
+    Level2,
+    Level3,
+    Level4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelInfo {
+    pub state: AppState,
+    /// Selects the level on the command line.
+    pub name: &'static str,
+    /// Shown in the level select menu.
+    pub title: &'static str,
+}
+
+/// All levels in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct LevelRegistry {
+    levels: Vec<LevelInfo>,
+}
+
+impl LevelRegistry {
+    pub fn register(&mut self, info: LevelInfo) {
+        self.levels.retain(|it| it.state != info.state);
+        self.levels.push(info);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&LevelInfo> {
+        self.levels.iter().find(|it| it.name == name)
+    }
+
+    pub fn levels(&self) -> impl Iterator<Item = &LevelInfo> {
+        self.levels.iter()
+    }
+}
+
+/// Name of the running level, empty in the level select menu.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CurrentLevel(pub String);
+
+/// Adds the systems of a level, gated to its state.
+/// Things that are not bound to one level, like events and assets, go to `app` directly.
+pub struct LevelBuilder<'a> {
+    pub app: &'a mut AppBuilder,
+    state: AppState,
+}
+
+impl<'a> LevelBuilder<'a> {
+    /// Registers the level, needs `LevelsPlugin`.
+    pub fn new(app: &'a mut AppBuilder, info: LevelInfo) -> Self {
+        app.resources_mut()
+            .get_mut::<LevelRegistry>()
+            .expect("add the LevelsPlugin before levels")
+            .register(info);
+
+        let state = info.state;
+        app.on_state_enter(
+            LEVEL_PRE_UPDATE_STAGE,
+            state,
+            level_snapshot_system.system(),
+        )
+        .on_state_exit(LEVEL_PRE_UPDATE_STAGE, state, level_cleanup_system.system());
+
+        Self { app, state }
+    }
+
+    /// Runs when the level starts, in place of a startup system.
+    pub fn on_enter<S: System<In = (), Out = ()>>(&mut self, system: S) -> &mut Self {
+        self.app
+            .on_state_enter(LEVEL_PRE_UPDATE_STAGE, self.state, system);
+        self
+    }
+
+    /// Runs when the level is left.
+    pub fn on_exit<S: System<In = (), Out = ()>>(&mut self, system: S) -> &mut Self {
+        self.app
+            .on_state_exit(LEVEL_PRE_UPDATE_STAGE, self.state, system);
+        self
+    }
+
+    pub fn add_system<S: System<In = (), Out = ()>>(&mut self, system: S) -> &mut Self {
+        self.app
+            .on_state_update(LEVEL_UPDATE_STAGE, self.state, system);
+        self
+    }
+
+    /// Runs before the update systems of the level, their commands are applied in between.
+    pub fn add_pre_update_system<S: System<In = (), Out = ()>>(&mut self, system: S) -> &mut Self {
+        self.app
+            .on_state_update(LEVEL_PRE_UPDATE_STAGE, self.state, system);
+        self
+    }
+
+    /// Resets a shared resource to its default when the level is left.
+    pub fn reset_resource_on_exit<T: Resource + Default>(&mut self) -> &mut Self {
+        self.on_exit(reset_resource::<T>.system())
+    }
+}
+
+/// The root entities that existed before the current state was entered.
+#[derive(Debug, Default)]
+struct LevelEntities {
+    before: HashSet<Entity>,
+}
+
+fn level_snapshot_system(
+    state: Res<State<AppState>>,
+    registry: Res<LevelRegistry>,
+    mut level_entities: ResMut<LevelEntities>,
+    mut current: ResMut<CurrentLevel>,
+    roots: Query<Entity, Without<Parent>>,
+) {
+    level_entities.before = roots.iter().collect();
+
+    let level = registry.levels().find(|it| it.state == *state.current());
+    current.0 = level.map_or(String::new(), |it| it.name.to_string());
+}
+
+/// Despawns everything the state spawned, their asset handles go with them.
+fn level_cleanup_system(
+    commands: &mut Commands,
+    level_entities: Res<LevelEntities>,
+    mut clear_color: ResMut<ClearColor>,
+    roots: Query<Entity, Without<Parent>>,
+) {
+    for entity in roots.iter() {
+        if !level_entities.before.contains(&entity) {
+            commands.despawn_recursive(entity);
+        }
+    }
+    *clear_color = ClearColor::default();
+}
+
+fn reset_resource<T: Resource + Default>(mut resource: ResMut<T>) {
+    *resource = T::default();
+}
+
+fn escape_system(
+    actions: Res<ActionState>,
+    mut state: ResMut<State<AppState>>,
+    mut exit: ResMut<Events<AppExit>>,
+) {
+    if actions.just_pressed(InputAction::Menu) {
+        if *state.current() == AppState::Menu {
+            exit.send(AppExit);
+        } else {
+            state.set_next(AppState::Menu).ok();
+        }
+    }
+}
+
+/// The text of the level select menu with the level at `cursor` marked.
+#[derive(Debug, Default)]
+struct MenuText {
+    cursor: usize,
+}
+
+impl MenuText {
+    fn text(&self, registry: &LevelRegistry) -> String {
+        let mut value = String::from("Select a level\n\n");
+        for (i, level) in registry.levels().enumerate() {
+            let marker = if i == self.cursor { ">" } else { " " };
+            value += &format!("{} {}  {}  {}\n", marker, i + 1, level.name, level.title);
+        }
+        value + "\nEsc  quit"
+    }
+}
+
+fn menu_setup(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<LevelRegistry>,
+) {
+    let font = asset_server.load("FiraSans-Bold.ttf");
+    let menu = MenuText::default();
+
+    commands.spawn(CameraUiBundle::default());
+    commands.spawn(TextBundle {
+        text: Text {
+            value: menu.text(&registry),
+            font,
+            style: TextStyle {
+                font_size: 28.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        },
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                left: Val::Px(48.0),
+                top: Val::Px(48.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    commands.with(menu);
+}
+
+fn menu_select_system(
+    actions: Res<ActionState>,
+    registry: Res<LevelRegistry>,
+    mut state: ResMut<State<AppState>>,
+    mut query: Query<(Mut<MenuText>, Mut<Text>)>,
+) {
+    let count = registry.levels().count();
+    if count == 0 {
+        return;
+    }
+
+    for (mut menu, mut text) in query.iter_mut() {
+        let step = if actions.just_pressed(InputAction::MoveDown) {
+            1
+        } else if actions.just_pressed(InputAction::MoveUp) {
+            count - 1
+        } else {
+            0
+        };
+        if step != 0 {
+            menu.cursor = (menu.cursor + step) % count;
+            text.value = menu.text(&registry);
+        }
+
+        let selected = match actions.slot_selection() {
+            Some(Some(slot)) => Some(slot),
+            _ if actions.just_pressed(InputAction::Interact) => Some(menu.cursor),
+            _ => None,
+        };
+        for level in selected.and_then(|it| registry.levels().nth(it)) {
+            state.set_next(level.state).ok();
+        }
+    }
+}
@@ -3,7 +3,7 @@
 mod bitpack;
 mod bitpack_map;
 mod bundle_utils;
-mod components;
+mod data_asset;
 mod interactions;
 mod map_asset;
 mod rapier_debug_render;
@@ -11,7 +11,13 @@ mod utils;
 
 pub mod assets;
 pub mod bevy_rapier_utils;
+pub mod collision_layer;
 pub mod commands_ext;
+pub mod components;
 pub mod entities;
+pub mod input;
 pub mod levels;
+pub mod replay;
+pub mod rng;
+pub mod save;
 pub mod systems;
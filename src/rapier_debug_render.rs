@@ -1,141 +1,300 @@
-use bevy::{
-    prelude::*,
-    render::mesh::{Indices, VertexAttributeValues},
+use bevy::{prelude::*, utils::HashMap};
+use bevy_prototype_lyon::prelude::{
+    point, primitive, ShapeType as LyonShape, StrokeOptions, TessellationMode,
 };
+use bevy_rapier2d::rapier::geometry::Shape;
 
-use crate::bevy_rapier_utils::*;
+use crate::{
+    bevy_rapier_utils::*,
+    input::{ActionState, InputAction},
+};
+
+/// Draws the outlines of all rapier colliders, toggled with the `ToggleRapierDebug` action.
+/// Sensors are drawn thin and yellow, sleeping bodies faded.
+/// Needs the `InputMapPlugin`.
+pub struct RapierDebugRenderPlugin;
+
+impl Plugin for RapierDebugRenderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<RapierDebugRender>()
+            .init_resource::<DebugGizmos>()
+            .add_system(rapier_debug_render_toggle.system())
+            .add_system(rapier_debug_render.system());
+    }
+}
+
+const DEBUG_Z: f32 = 100.0;
+const CIRCLE_SEGMENTS: usize = 16;
+const LINE_WIDTH: f32 = 1.0;
+const SENSOR_LINE_WIDTH: f32 = 0.5;
+const SENSOR_COLOR: Color = Color::rgba(1.0, 0.9, 0.2, 0.8);
+const SLEEPING_ALPHA: f32 = 0.3;
+
+#[derive(Default)]
+pub struct RapierDebugRender {
+    pub enabled: bool,
+}
+
+struct DebugGizmo {
+    root: Entity,
+    look: GizmoLook,
+}
+
+/// How a collider was drawn, it is drawn again when any of it changes.
+#[derive(PartialEq)]
+struct GizmoLook {
+    body: RigidBodyHandle,
+    position: Isometry<f32>,
+    color: Color,
+    line_width: f32,
+}
+
+/// Gizmos by collider entity.
+#[derive(Default)]
+struct DebugGizmos(HashMap<Entity, DebugGizmo>);
+
+struct DebugGizmoRoot;
 
-fn get_color(
+fn rapier_debug_render_toggle(actions: Res<ActionState>, mut config: ResMut<RapierDebugRender>) {
+    if actions.just_pressed(InputAction::ToggleRapierDebug) {
+        config.enabled = !config.enabled;
+    }
+}
+
+fn debug_color(
     body: &RigidBody,
     collider: &Collider,
     debug_color: Option<&RapierRenderColor>,
 ) -> Color {
-    let base = if collider.is_sensor() { 0.6 } else { 0.4 };
-    let light = base + 0.2 * rand::random::<f32>();
-    let default_color = match body.body_status {
-        BodyStatus::Static => Color::rgb(0.2 + light, light, light),
-        BodyStatus::Dynamic => Color::rgb(light, 0.2 + light, light),
-        BodyStatus::Kinematic => Color::rgb(light, light, 0.2 + light),
+    let mut color = match (collider.is_sensor(), debug_color) {
+        (true, _) => SENSOR_COLOR,
+        (false, Some(c)) => Color::rgb(c.0, c.1, c.2),
+        (false, None) => match body.body_status {
+            BodyStatus::Static => Color::rgb(0.8, 0.4, 0.4),
+            BodyStatus::Dynamic => Color::rgb(0.4, 0.8, 0.4),
+            BodyStatus::Kinematic => Color::rgb(0.4, 0.4, 0.8),
+        },
     };
-
-    let mut color = debug_color
-        .map(|c| Color::rgb(c.0, c.1, c.2))
-        .unwrap_or(default_color);
-    color.set_a(0.5);
+    if body.is_sleeping() {
+        color.set_a(SLEEPING_ALPHA);
+    }
     color
 }
 
-fn _sync_transform(pos: &Isometry<f32>, scale: f32, transform: &mut Transform) {
-    // Do not touch the 'z' part of the translation, used in Bevy for 2d layering
-    transform.translation.x = pos.translation.vector.x * scale;
-    transform.translation.y = pos.translation.vector.y * scale;
-
-    let rot = na::UnitQuaternion::new(na::Vector3::z() * pos.rotation.angle());
-    transform.rotation = Quat::from_xyzw(rot.i, rot.j, rot.k, rot.w);
-}
-
-/// System responsible for attaching a PbrBundle to each entity having a collider.
-pub fn rapier_debug_render(
+/// Spawns, moves and despawns the outlines of colliders, they follow the body of the collider.
+fn rapier_debug_render(
     commands: &mut Commands,
+    config: Res<RapierDebugRender>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
     configuration: Res<RapierConfiguration>,
     bodies: Res<RigidBodySet>,
-    colliders: ResMut<ColliderSet>,
-    query: Query<
-        (Entity, &ColliderHandleComponent, Option<&RapierRenderColor>),
-        Without<Handle<Mesh>>,
-    >,
+    colliders: Res<ColliderSet>,
+    mut gizmos: ResMut<DebugGizmos>,
+    query: Query<(Entity, &ColliderHandleComponent, Option<&RapierRenderColor>)>,
+    mut roots: Query<Mut<Transform>, With<DebugGizmoRoot>>,
 ) {
-    for (entity, collider, debug_color) in &mut query.iter() {
-        if let Some(collider) = colliders.get(collider.handle()) {
-            if let Some(body) = bodies.get(collider.parent()) {
-                let shape = collider.shape();
-                let mesh = match shape.shape_type() {
-                    #[cfg(feature = "dim3")]
-                    ShapeType::Cuboid => Mesh::from(shape::Cube { size: 2.0 }),
-                    ShapeType::Cuboid => Mesh::from(shape::Quad {
-                        size: Vec2::new(2.0, 2.0),
-                        flip: false,
-                    }),
-                    ShapeType::Ball => Mesh::from(shape::Icosphere {
-                        subdivisions: 1,
-                        radius: 1.0,
-                    }),
-
-                    ShapeType::Trimesh => {
-                        let mut mesh =
-                            Mesh::new(bevy::render::pipeline::PrimitiveTopology::TriangleList);
-                        let trimesh = shape.as_trimesh().unwrap();
-                        mesh.set_attribute(
-                            Mesh::ATTRIBUTE_POSITION,
-                            VertexAttributeValues::from(
-                                trimesh
-                                    .vertices()
-                                    .iter()
-                                    .map(|vertice| [vertice.x, vertice.y])
-                                    .collect::<Vec<_>>(),
-                            ),
-                        );
-                        mesh.set_indices(Some(Indices::U32(
-                            trimesh
-                                .indices()
-                                .iter()
-                                .flat_map(|triangle| triangle.iter())
-                                .cloned()
-                                .collect(),
-                        )));
-                        mesh
-                    }
-                    _ => unimplemented!(),
-                };
-
-                let scale = match shape.shape_type() {
-                    ShapeType::Cuboid => {
-                        let c = shape.as_cuboid().unwrap();
-                        Vec3::new(c.half_extents.x, c.half_extents.y, 1.0)
-                    }
-                    #[cfg(feature = "dim3")]
-                    ShapeType::Cuboid => {
-                        let c = shape.as_cuboid().unwrap();
-                        Vec3::from_slice_unaligned(c.half_extents.as_slice())
-                    }
-                    ShapeType::Ball => {
-                        let b = shape.as_ball().unwrap();
-                        Vec3::new(b.radius, b.radius, b.radius)
-                    }
-                    ShapeType::Trimesh => Vec3::one(),
-                    _ => unimplemented!(),
-                } * configuration.scale;
-
-                let mut transform = Transform::from_scale(scale);
-                _sync_transform(
-                    collider.position_wrt_parent(),
-                    configuration.scale,
-                    &mut transform,
-                );
-
-                if let ShapeType::Ball = shape.shape_type() {
-                    transform.translation.z -= scale.x;
+    if !config.enabled {
+        for (_, gizmo) in gizmos.0.drain() {
+            commands.despawn_recursive(gizmo.root);
+        }
+        return;
+    }
+
+    let scale = configuration.scale;
+    let mut seen = Vec::new();
+
+    for (entity, handle, render_color) in query.iter() {
+        let (collider, body) = match colliders
+            .get(handle.handle())
+            .and_then(|collider| Some((collider, bodies.get(collider.parent())?)))
+        {
+            Some(it) => it,
+            None => continue,
+        };
+        seen.push(entity);
+
+        let body_position = body.position();
+        let translation = Vec3::new(
+            body_position.translation.x * scale,
+            body_position.translation.y * scale,
+            DEBUG_Z,
+        );
+        let rotation = Quat::from_rotation_z(body_position.rotation.angle());
+
+        let look = GizmoLook {
+            body: collider.parent(),
+            position: *collider.position_wrt_parent(),
+            color: debug_color(body, collider, render_color),
+            line_width: if collider.is_sensor() {
+                SENSOR_LINE_WIDTH
+            } else {
+                LINE_WIDTH
+            },
+        };
+
+        if let Some(gizmo) = gizmos.0.get(&entity) {
+            if gizmo.look == look {
+                for mut transform in roots.get_mut(gizmo.root) {
+                    transform.translation = translation;
+                    transform.rotation = rotation;
                 }
+                continue;
+            }
+            commands.despawn_recursive(gizmo.root);
+        }
 
-                let ground_pbr = PbrBundle {
-                    transform,
-                    mesh: meshes.add(mesh),
-                    visible: Visible {
-                        is_transparent: true,
-                        is_visible: true,
-                    },
-                    material: materials.add(StandardMaterial {
-                        albedo: get_color(body, collider, debug_color),
-                        albedo_texture: None,
-                        shaded: false,
-                    }),
+        let outlines = shape_outlines(collider.shape(), &look.position);
+        let material = materials.add(look.color.into());
+        let stroke = StrokeOptions::default().with_line_width(look.line_width);
+        let meshes = &mut meshes;
+
+        let root = commands
+            .spawn((
+                DebugGizmoRoot,
+                Transform {
+                    translation,
+                    rotation,
                     ..Default::default()
-                };
+                },
+                GlobalTransform::default(),
+            ))
+            .with_children(|parent| {
+                for (points, closed) in outlines {
+                    let points = points.iter().map(|it| point(it.x * scale, it.y * scale));
+                    parent.spawn(primitive(
+                        material.clone(),
+                        meshes,
+                        LyonShape::Polyline {
+                            points: points.collect(),
+                            closed,
+                        },
+                        TessellationMode::Stroke(&stroke),
+                        Vec3::zero(),
+                    ));
+                }
+            })
+            .current_entity()
+            .unwrap();
 
-                commands.insert(entity, ground_pbr);
-            }
+        gizmos.0.insert(entity, DebugGizmo { root, look });
+    }
+
+    let removed: Vec<Entity> = gizmos
+        .0
+        .keys()
+        .filter(|it| !seen.contains(it))
+        .cloned()
+        .collect();
+    for entity in removed {
+        for gizmo in gizmos.0.remove(&entity) {
+            commands.despawn_recursive(gizmo.root);
+        }
+    }
+}
+
+/// Outlines of a shape in body space, each a list of points and whether it is closed.
+/// Shapes without an outline, like height fields, give none.
+fn shape_outlines(shape: &dyn Shape, position: &Isometry<f32>) -> Vec<(Vec<Vec2>, bool)> {
+    let to_vec2 = |p: na::Point2<f32>| {
+        let p = position * p;
+        Vec2::new(p.x, p.y)
+    };
+
+    match shape.shape_type() {
+        ShapeType::Ball => {
+            let radius = shape.as_ball().unwrap().radius;
+            vec![(
+                arc(radius, 0.0, std::f32::consts::TAU, CIRCLE_SEGMENTS)
+                    .map(|it| to_vec2(na::Point2::new(it.x, it.y)))
+                    .collect(),
+                true,
+            )]
+        }
+        ShapeType::Cuboid => {
+            let e = shape.as_cuboid().unwrap().half_extents;
+            let corners = [(-e.x, -e.y), (e.x, -e.y), (e.x, e.y), (-e.x, e.y)];
+            vec![(
+                corners
+                    .iter()
+                    .map(|(x, y)| to_vec2(na::Point2::new(*x, *y)))
+                    .collect(),
+                true,
+            )]
+        }
+        ShapeType::Capsule => {
+            let capsule = shape.as_capsule().unwrap();
+            let (a, b) = (capsule.a, capsule.b);
+            let axis = b - a;
+            let angle = axis.y.atan2(axis.x);
+            let half = CIRCLE_SEGMENTS / 2;
+            let pi = std::f32::consts::PI;
+
+            // half circles around both ends, joined by the closing lines
+            let around = |center: na::Point2<f32>, from: f32| {
+                arc(capsule.radius, from, pi, half)
+                    .map(move |it| to_vec2(center + na::Vector2::new(it.x, it.y)))
+            };
+            let points = around(b, angle - 0.5 * pi)
+                .chain(around(a, angle + 0.5 * pi))
+                .collect();
+            vec![(points, true)]
+        }
+        ShapeType::Segment => {
+            let segment = shape.as_segment().unwrap();
+            vec![(vec![to_vec2(segment.a), to_vec2(segment.b)], false)]
+        }
+        ShapeType::Triangle => {
+            let t = shape.as_triangle().unwrap();
+            vec![(vec![to_vec2(t.a), to_vec2(t.b), to_vec2(t.c)], true)]
         }
+        ShapeType::Polyline => {
+            let polyline = shape.as_polyline().unwrap();
+            vec![(
+                polyline.vertices().iter().cloned().map(to_vec2).collect(),
+                false,
+            )]
+        }
+        ShapeType::ConvexPolygon => {
+            let polygon = shape.as_convex_polygon().unwrap();
+            vec![(
+                polygon.points().iter().cloned().map(to_vec2).collect(),
+                true,
+            )]
+        }
+        ShapeType::Trimesh => {
+            let trimesh = shape.as_trimesh().unwrap();
+            let vertices = trimesh.vertices();
+            trimesh
+                .indices()
+                .iter()
+                .map(|triangle| {
+                    let points = triangle
+                        .iter()
+                        .map(|i| to_vec2(vertices[*i as usize]))
+                        .collect();
+                    (points, true)
+                })
+                .collect()
+        }
+        ShapeType::Compound => shape
+            .as_compound()
+            .unwrap()
+            .shapes()
+            .iter()
+            .flat_map(|(part_position, part)| shape_outlines(&**part, &(position * part_position)))
+            .collect(),
+        _ => Vec::new(),
     }
 }
+
+/// `segments` points on a circle around the origin, starting at angle `from`.
+fn arc(radius: f32, from: f32, angle: f32, segments: usize) -> impl Iterator<Item = Vec2> {
+    let closed = (angle - std::f32::consts::TAU).abs() < f32::EPSILON;
+    let steps = if closed { segments } else { segments + 1 };
+
+    (0..steps).map(move |i| {
+        let a = from + angle * i as f32 / segments as f32;
+        Vec2::new(a.cos(), a.sin()) * radius
+    })
+}
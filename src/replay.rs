@@ -0,0 +1,408 @@
+use std::{fmt::Write as _, path::PathBuf};
+
+use bevy::{app::AppExit, prelude::*, utils::HashSet};
+
+use crate::{
+    bevy_rapier_utils::{IntegrationParameters, RapierConfiguration},
+    entities::player::PlayerId,
+    input::{ActionState, InputAction},
+};
+
+/// Records the logical input of every frame together with the RNG seed,
+/// or feeds a recording back in place of the live input.
+///
+/// `BEVY_THING_RECORD=oven.replay` records until the app exits,
+/// `BEVY_THING_REPLAY=oven.replay` replays and continues with live input afterwards.
+/// Both run gameplay with a fixed `GameTime` delta and step rapier once per frame
+/// with the same delta, so a replay repeats the recorded run.
+///
+/// Still not covered: mouse pointer input, assets that finish loading on another frame,
+/// and rapier itself across platforms, as it is built without its cross-platform determinism.
+/// Add it after `InputMapPlugin` and `RapierPhysicsPlugin`.
+pub struct ReplayPlugin;
+
+pub const RECORD_ENV: &'static str = "BEVY_THING_RECORD";
+pub const REPLAY_ENV: &'static str = "BEVY_THING_REPLAY";
+pub const REPLAY_STAGE: &'static str = "replay";
+
+const FIXED_DELTA: f32 = 1.0 / 60.0;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let replay = InputReplay::from_env();
+        let fixed = replay.is_active().then_some(FIXED_DELTA);
+
+        app.resources_mut().insert(RngSeed(replay.seed()));
+        app.resources_mut().insert(GameTime::new(fixed));
+        app.resources_mut().insert(replay);
+
+        app.add_stage_after(stage::PRE_UPDATE, REPLAY_STAGE, SystemStage::parallel())
+            .add_system_to_stage(stage::FIRST, game_time_system.system())
+            .add_system_to_stage(REPLAY_STAGE, replay_input_system.system())
+            .add_system_to_stage(stage::LAST, save_recording_system.system());
+
+        if fixed.is_some() {
+            app.add_system_to_stage(stage::FIRST, fixed_physics_step_system.system());
+        }
+    }
+}
+
+#[test]
+fn recording_roundtrip() {
+    let mut recording = InputRecording::new(42);
+    let mut state = ActionState::default();
+
+    let mut pressed = HashSet::default();
+    pressed.insert(InputAction::Interact);
+    pressed.insert(InputAction::SelectSlot(2));
+    state.update(pressed, Vec2::new(0.5, -1.0));
+
+    let mut frame = RecordedFrame::default();
+    frame.push(Channel::Player(PlayerId(1)), &state);
+    recording.frames.push(RecordedFrame::default());
+    recording.frames.push(frame);
+    recording.frames.push(RecordedFrame::default());
+
+    let parsed = InputRecording::parse(&recording.write()).unwrap();
+    assert_eq!(parsed.seed, 42);
+    assert_eq!(parsed.frames.len(), 3);
+
+    let replayed = parsed.state(1, Channel::Player(PlayerId(1)));
+    assert!(replayed.just_pressed(InputAction::Interact));
+    assert!(replayed.pressed(InputAction::SelectSlot(2)));
+    assert_eq!(replayed.stick(), Vec2::new(0.5, -1.0));
+    assert!(!parsed
+        .state(1, Channel::Global)
+        .pressed(InputAction::Interact));
+    assert!(parsed
+        .state(2, Channel::Player(PlayerId(1)))
+        .just_released(InputAction::Interact));
+}
+
+/// Seed of the run, recorded so a replay draws the same random numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngSeed(pub u64);
+
+/// Frame delta for gameplay systems, fixed while recording or replaying.
+#[derive(Debug, Clone, Copy)]
+pub struct GameTime {
+    delta_seconds: f32,
+    elapsed_seconds: f64,
+    fixed: Option<f32>,
+    frame: u64,
+}
+
+impl GameTime {
+    pub fn new(fixed: Option<f32>) -> Self {
+        Self {
+            delta_seconds: 0.0,
+            elapsed_seconds: 0.0,
+            fixed,
+            frame: 0,
+        }
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta_seconds
+    }
+
+    pub fn elapsed_seconds(&self) -> f64 {
+        self.elapsed_seconds
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+impl Default for GameTime {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+pub fn game_time_system(time: Res<Time>, mut game_time: ResMut<GameTime>) {
+    game_time.delta_seconds = game_time.fixed.unwrap_or_else(|| time.delta_seconds());
+    game_time.elapsed_seconds += game_time.delta_seconds as f64;
+    game_time.frame += 1;
+}
+
+/// Levels set up their own `RapierConfiguration`, so the fixed step is enforced every frame.
+pub fn fixed_physics_step_system(
+    game_time: Res<GameTime>,
+    mut config: ResMut<RapierConfiguration>,
+    mut integration: ResMut<IntegrationParameters>,
+) {
+    for fixed in game_time.fixed {
+        if config.time_dependent_number_of_timesteps {
+            config.time_dependent_number_of_timesteps = false;
+        }
+        if integration.dt() != fixed {
+            integration.set_dt(fixed);
+        }
+    }
+}
+
+/// Which `ActionState` an input belongs to, the resource or the one of a player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Global,
+    Player(PlayerId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedInput {
+    pub channel: Channel,
+    pub pressed: Vec<InputAction>,
+    pub stick: Vec2,
+}
+
+/// Only channels with input are stored, missing channels replay as released.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordedFrame {
+    pub inputs: Vec<RecordedInput>,
+}
+
+impl RecordedFrame {
+    pub fn push(&mut self, channel: Channel, state: &ActionState) {
+        let pressed: Vec<InputAction> = state.pressed_actions().cloned().collect();
+        if !pressed.is_empty() || state.stick() != Vec2::zero() {
+            self.inputs.push(RecordedInput {
+                channel,
+                pressed,
+                stick: state.stick(),
+            });
+        }
+    }
+
+    fn input(&self, channel: Channel) -> (HashSet<InputAction>, Vec2) {
+        self.inputs
+            .iter()
+            .find(|input| input.channel == channel)
+            .map(|input| (input.pressed.iter().cloned().collect(), input.stick))
+            .unwrap_or_default()
+    }
+}
+
+/// Text format, one line per channel and frame with input:
+///
+/// ```text
+/// seed 42
+/// frames 600
+/// 12 * MoveUp,Interact 0 0
+/// 12 0 - 0.5 0
+/// ```
+///
+/// `*` is the `ActionState` resource, numbers are player ids.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputRecording {
+    pub seed: u64,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            frames: vec![],
+        }
+    }
+
+    /// The action state of `channel` at `frame`, with just pressed and released
+    /// derived from the frame before like the live input does.
+    pub fn state(&self, frame: usize, channel: Channel) -> ActionState {
+        let mut state = ActionState::default();
+        if let Some(previous) = frame.checked_sub(1).and_then(|it| self.frames.get(it)) {
+            let (pressed, stick) = previous.input(channel);
+            state.update(pressed, stick);
+        }
+        if let Some(current) = self.frames.get(frame) {
+            let (pressed, stick) = current.input(channel);
+            state.update(pressed, stick);
+        }
+        state
+    }
+
+    pub fn write(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "seed {}", self.seed);
+        let _ = writeln!(text, "frames {}", self.frames.len());
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            for input in frame.inputs.iter() {
+                let channel = match input.channel {
+                    Channel::Global => "*".to_string(),
+                    Channel::Player(id) => id.0.to_string(),
+                };
+                let pressed = if input.pressed.is_empty() {
+                    "-".to_string()
+                } else {
+                    let names: Vec<String> = input.pressed.iter().map(|it| it.name()).collect();
+                    names.join(",")
+                };
+                let _ = writeln!(
+                    text,
+                    "{} {} {} {} {}",
+                    index, channel, pressed, input.stick.x, input.stick.y
+                );
+            }
+        }
+
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut recording = Self::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = || anyhow::anyhow!("line {}: invalid {:?}", index + 1, line);
+            let line = line.split('#').next().unwrap_or("").trim();
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            match parts.as_slice() {
+                [] => {}
+                ["seed", seed] => recording.seed = seed.parse()?,
+                ["frames", frames] => {
+                    recording.frames = vec![RecordedFrame::default(); frames.parse()?];
+                }
+                [frame, channel, pressed, x, y] => {
+                    let channel = match *channel {
+                        "*" => Channel::Global,
+                        id => Channel::Player(PlayerId(id.parse()?)),
+                    };
+                    let pressed = pressed
+                        .split(',')
+                        .filter(|it| *it != "-")
+                        .map(|it| InputAction::parse(it).ok_or_else(error))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let stick = Vec2::new(x.parse()?, y.parse()?);
+
+                    let frame = recording
+                        .frames
+                        .get_mut(frame.parse::<usize>()?)
+                        .ok_or_else(error)?;
+                    frame.inputs.push(RecordedInput {
+                        channel,
+                        pressed,
+                        stick,
+                    });
+                }
+                _ => return Err(error()),
+            }
+        }
+
+        Ok(recording)
+    }
+}
+
+pub enum InputReplay {
+    Off {
+        seed: u64,
+    },
+    Record {
+        path: PathBuf,
+        recording: InputRecording,
+    },
+    Replay {
+        recording: InputRecording,
+        frame: usize,
+    },
+}
+
+impl InputReplay {
+    pub fn from_env() -> Self {
+        if let Some(path) = std::env::var_os(REPLAY_ENV) {
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| InputRecording::parse(&text));
+
+            match loaded {
+                Ok(recording) => {
+                    return InputReplay::Replay {
+                        recording,
+                        frame: 0,
+                    }
+                }
+                Err(err) => bevy::log::warn!("cannot replay {:?}: {}", path, err),
+            }
+        }
+
+        let seed = rand::random::<u64>();
+
+        match std::env::var_os(RECORD_ENV) {
+            Some(path) => InputReplay::Record {
+                path: path.into(),
+                recording: InputRecording::new(seed),
+            },
+            None => InputReplay::Off { seed },
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        match self {
+            InputReplay::Off { seed } => *seed,
+            InputReplay::Record { recording, .. } | InputReplay::Replay { recording, .. } => {
+                recording.seed
+            }
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self, InputReplay::Off { .. })
+    }
+}
+
+/// Runs after the live input is read, so it either records it or overwrites it.
+/// Only logical actions are covered, mouse pointer input is always live.
+pub fn replay_input_system(
+    mut replay: ResMut<InputReplay>,
+    mut global: ResMut<ActionState>,
+    mut players: Query<(&PlayerId, Mut<ActionState>)>,
+) {
+    let mut finished = None;
+
+    match &mut *replay {
+        InputReplay::Off { .. } => {}
+        InputReplay::Record { recording, .. } => {
+            let mut frame = RecordedFrame::default();
+            frame.push(Channel::Global, &global);
+            for (id, state) in players.iter_mut() {
+                frame.push(Channel::Player(*id), &state);
+            }
+            recording.frames.push(frame);
+        }
+        InputReplay::Replay { recording, frame } => {
+            if *frame < recording.frames.len() {
+                *global = recording.state(*frame, Channel::Global);
+                for (id, mut state) in players.iter_mut() {
+                    *state = recording.state(*frame, Channel::Player(*id));
+                }
+                *frame += 1;
+            } else {
+                bevy::log::info!("replay finished after {} frames", frame);
+                finished = Some(recording.seed);
+            }
+        }
+    }
+
+    for seed in finished {
+        *replay = InputReplay::Off { seed };
+    }
+}
+
+pub fn save_recording_system(
+    replay: Res<InputReplay>,
+    mut event_reader: Local<EventReader<AppExit>>,
+    events: Res<Events<AppExit>>,
+) {
+    if event_reader.iter(&events).next().is_none() {
+        return;
+    }
+
+    if let InputReplay::Record { path, recording } = &*replay {
+        match std::fs::write(path, recording.write()) {
+            Ok(()) => bevy::log::info!("recorded {} frames to {:?}", recording.frames.len(), path),
+            Err(err) => bevy::log::warn!("cannot write recording {:?}: {}", path, err),
+        }
+    }
+}
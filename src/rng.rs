@@ -0,0 +1,83 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::{prng::XorShiftRng, SeedableRng};
+
+use crate::replay::RngSeed;
+
+#[test]
+fn streams_are_reproducible() {
+    use rand::Rng;
+
+    let mut a = GameRng::new(7);
+    let mut b = GameRng::new(7);
+
+    let _ = b.stream("other").gen::<u64>();
+
+    let draws_a: Vec<u32> = (0..8).map(|_| a.stream("test").gen()).collect();
+    let draws_b: Vec<u32> = (0..8).map(|_| b.stream("test").gen()).collect();
+    assert_eq!(draws_a, draws_b);
+
+    let mut c = GameRng::new(8);
+    let draws_c: Vec<u32> = (0..8).map(|_| c.stream("test").gen()).collect();
+    assert_ne!(draws_a, draws_c);
+}
+
+pub type GameRngStream = XorShiftRng;
+
+/// Source of all gameplay randomness, seeded from `RngSeed` when there is one.
+///
+/// Every system draws from its own named stream, so adding draws to one system
+/// does not shift the numbers another system gets.
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<&'static str, GameRngStream>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: HashMap::default(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, name: &'static str) -> &mut GameRngStream {
+        let seed = self.seed;
+        self.streams
+            .entry(name)
+            .or_insert_with(|| stream_rng(seed, name))
+    }
+}
+
+impl FromResources for GameRng {
+    fn from_resources(resources: &Resources) -> Self {
+        let seed = resources
+            .get::<RngSeed>()
+            .map(|seed| seed.0)
+            .unwrap_or_else(rand::random);
+        Self::new(seed)
+    }
+}
+
+fn stream_rng(seed: u64, name: &str) -> GameRngStream {
+    // FNV-1a of the name, mixed with the seed by splitmix64
+    let name_hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    let mut state = seed ^ name_hash;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&next().to_le_bytes());
+    bytes[8..].copy_from_slice(&next().to_le_bytes());
+    GameRngStream::from_seed(bytes)
+}
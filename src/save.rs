@@ -0,0 +1,481 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bevy_rapier_utils::*,
+    components::Marker,
+    entities::{
+        player::PlayerId, spawn_world_item_records, world_item_records, Caption, Journal,
+        JournalEntry, OvenState, Subject, WorldItem, WorldItemRecord,
+    },
+    input::{ActionState, InputAction},
+    interactions::{show_openable, Openable},
+    levels::{
+        level2::{Tile, TileMapSpawner},
+        CurrentLevel,
+    },
+    systems::inventory::{Inventory, Item, Items},
+};
+
+/// Writes the `Saved` entities, the world items and the journal to a RON file on the
+/// `QuickSave` action and puts them back on the `QuickLoad` action.
+///
+/// A load applies to the entities the level spawned from its tilemap, matched by their
+/// name `String`, `PlayerId` or `Tile`, and only in the level it was saved in.
+/// Saved players that did not join yet are applied once they show up, other saved entities
+/// that are not in the level once its tilemap spawned are dropped. World items are not matched,
+/// the ones lying around are replaced by the saved ones.
+/// The outcome is shown as a `Caption`.
+/// Needs rapier, the `Item` asset, the `Items` resource, the `InputMapPlugin`
+/// and the `JournalPlugin`.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<QuickSave>()
+            .init_resource::<PendingLoad>()
+            .init_resource::<CurrentLevel>()
+            .add_system(quick_save_system.system())
+            .add_system(quick_load_system.system())
+            .add_system(apply_load_system.system());
+    }
+}
+
+#[test]
+fn save_game_roundtrip() {
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        level: "level4".into(),
+        entities: vec![SavedEntity {
+            key: SaveKey::Tile {
+                tile: 'o',
+                col: 12,
+                row: 1,
+            },
+            position: Some((192.0, -16.0)),
+            inventory: Some(vec!["fish".into()]),
+            oven: Some(SavedOven {
+                elapsed: 1.5,
+                duration: 3.0,
+                item: Some("fish".into()),
+                baked_item: Some("baked_fish".into()),
+                on_fire: true,
+            }),
+            open: None,
+        }],
+        world_items: vec![WorldItemRecord {
+            item: "baked_fish".into(),
+            x: 4.0,
+            y: -8.0,
+        }],
+        journal: vec![SavedJournalEntry {
+            subject: SavedSubject::Marker("Door".into()),
+            text: "A door.".into(),
+            discovered_at: 2.0,
+            times_observed: 3,
+        }],
+    };
+
+    let parsed = SaveGame::parse(&save.write().unwrap()).unwrap();
+    assert_eq!(parsed, save);
+
+    let old = SaveGame {
+        version: SAVE_VERSION + 1,
+        ..save
+    };
+    assert!(SaveGame::parse(&old.write().unwrap()).is_err());
+}
+
+/// Bumped whenever the format changes, older files are rejected.
+pub const SAVE_VERSION: u32 = 3;
+
+/// Entities with this are written to save files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Saved;
+
+/// Where to quick save and quick load.
+#[derive(Debug, Clone)]
+pub struct QuickSave {
+    pub path: PathBuf,
+    /// Depth of the world items a load spawns.
+    pub world_item_z: f32,
+}
+
+impl Default for QuickSave {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("quick.save.ron"),
+            world_item_z: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    /// Name of the level, see `CurrentLevel`.
+    pub level: String,
+    pub entities: Vec<SavedEntity>,
+    pub world_items: Vec<WorldItemRecord>,
+    pub journal: Vec<SavedJournalEntry>,
+}
+
+impl SaveGame {
+    pub fn write(&self) -> anyhow::Result<String> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let save: SaveGame = ron::de::from_str(text)?;
+        if save.version != SAVE_VERSION {
+            anyhow::bail!(
+                "save version {} is not supported, expected {}",
+                save.version,
+                SAVE_VERSION
+            );
+        }
+        Ok(save)
+    }
+}
+
+/// How a saved entity is found again after the level spawned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SaveKey {
+    Named(String),
+    Player(u8),
+    Tile { tile: char, col: u32, row: u32 },
+}
+
+impl SaveKey {
+    fn of(name: Option<&String>, player: Option<&PlayerId>, tile: Option<&Tile>) -> Option<Self> {
+        match (name, player, tile) {
+            (Some(name), _, _) => Some(SaveKey::Named(name.clone())),
+            (None, Some(id), _) => Some(SaveKey::Player(id.0)),
+            (None, None, Some(tile)) => Some(SaveKey::Tile {
+                tile: tile.0 as char,
+                col: tile.1,
+                row: tile.2,
+            }),
+            (None, None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub key: SaveKey,
+    pub position: Option<(f32, f32)>,
+    /// Item names.
+    pub inventory: Option<Vec<String>>,
+    pub oven: Option<SavedOven>,
+    pub open: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedOven {
+    pub elapsed: f32,
+    pub duration: f32,
+    pub item: Option<String>,
+    pub baked_item: Option<String>,
+    pub on_fire: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SavedSubject {
+    Marker(String),
+    Item(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedJournalEntry {
+    pub subject: SavedSubject,
+    pub text: String,
+    pub discovered_at: f64,
+    pub times_observed: u32,
+}
+
+/// Saved entities that were not matched yet, levels reset it when they are left.
+#[derive(Debug, Default)]
+pub struct PendingLoad {
+    entities: Vec<SavedEntity>,
+}
+
+fn item_name(assets: &Assets<Item>, item: &Handle<Item>) -> Option<String> {
+    assets.get(item).map(|it| it.name.to_string())
+}
+
+fn item_by_name(assets: &Assets<Item>, name: &str) -> Option<Handle<Item>> {
+    assets
+        .iter()
+        .find(|(_, item)| item.name == name)
+        .map(|(id, _)| assets.get_handle(id))
+}
+
+fn quick_save_system(
+    actions: Res<ActionState>,
+    quick_save: Res<QuickSave>,
+    current_level: Res<CurrentLevel>,
+    journal: Res<Journal>,
+    mut captions: ResMut<Events<Caption>>,
+    bodies: Res<RigidBodySet>,
+    item_assets: Res<Assets<Item>>,
+    query: Query<
+        (
+            Option<&String>,
+            Option<&PlayerId>,
+            Option<&Tile>,
+            &Transform,
+            Option<&RigidBodyHandleComponent>,
+            Option<&Inventory>,
+            Option<&OvenState>,
+            Option<&Openable>,
+        ),
+        With<Saved>,
+    >,
+    world_item_query: Query<(&WorldItem, &Transform)>,
+) {
+    if !actions.just_pressed(InputAction::QuickSave) {
+        return;
+    }
+
+    let mut entities = vec![];
+    for (name, player, tile, trans, body, inventory, oven, openable) in query.iter() {
+        let key = match SaveKey::of(name, player, tile) {
+            Some(key) => key,
+            None => continue,
+        };
+        let position = match body.and_then(|it| bodies.get(it.handle())) {
+            Some(body) => {
+                let translation = body.position().translation;
+                (translation.x, translation.y)
+            }
+            None => (trans.translation.x, trans.translation.y),
+        };
+        let names = |items: &[Handle<Item>]| {
+            items
+                .iter()
+                .filter_map(|it| item_name(&item_assets, it))
+                .collect()
+        };
+
+        entities.push(SavedEntity {
+            key,
+            position: Some(position),
+            inventory: inventory.map(|it| names(&it.items)),
+            oven: oven.map(|oven| SavedOven {
+                elapsed: oven.baking_timer.elapsed(),
+                duration: oven.baking_timer.duration(),
+                item: oven
+                    .item
+                    .as_ref()
+                    .and_then(|it| item_name(&item_assets, it)),
+                baked_item: oven
+                    .baked_item
+                    .as_ref()
+                    .and_then(|it| item_name(&item_assets, it)),
+                on_fire: oven.on_fire,
+            }),
+            open: openable.map(|it| it.open),
+        });
+    }
+
+    let journal = journal
+        .entries
+        .iter()
+        .map(|entry| SavedJournalEntry {
+            subject: match &entry.subject {
+                Subject::Marker(marker) => SavedSubject::Marker(format!("{:?}", marker)),
+                Subject::Item(name) => SavedSubject::Item(name.clone()),
+            },
+            text: entry.text.clone(),
+            discovered_at: entry.discovered_at,
+            times_observed: entry.times_observed,
+        })
+        .collect();
+
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        level: current_level.0.clone(),
+        entities,
+        world_items: world_item_records(&item_assets, &world_item_query),
+        journal,
+    };
+    let written = save
+        .write()
+        .and_then(|text| Ok(std::fs::write(&quick_save.path, text)?));
+    match written {
+        Ok(()) => captions.send(Caption("Game saved".into())),
+        Err(err) => {
+            bevy::log::warn!("could not save {}: {}", quick_save.path.display(), err);
+            captions.send(Caption("Could not save the game".into()));
+        }
+    }
+}
+
+fn quick_load_system(
+    commands: &mut Commands,
+    actions: Res<ActionState>,
+    quick_save: Res<QuickSave>,
+    current_level: Res<CurrentLevel>,
+    items: Res<Items>,
+    item_assets: Res<Assets<Item>>,
+    mut journal: ResMut<Journal>,
+    mut captions: ResMut<Events<Caption>>,
+    mut pending: ResMut<PendingLoad>,
+    world_item_query: Query<Entity, With<WorldItem>>,
+) {
+    if !actions.just_pressed(InputAction::QuickLoad) {
+        return;
+    }
+
+    let save = std::fs::read_to_string(&quick_save.path)
+        .map_err(anyhow::Error::from)
+        .and_then(|text| SaveGame::parse(&text));
+    let save = save.and_then(|save| {
+        if save.level != current_level.0 {
+            anyhow::bail!("it was saved in {:?}", save.level);
+        }
+        Ok(save)
+    });
+    let save = match save {
+        Ok(save) => save,
+        Err(err) => {
+            bevy::log::warn!("could not load {}: {}", quick_save.path.display(), err);
+            captions.send(Caption("Could not load the game".into()));
+            return;
+        }
+    };
+
+    journal.entries = save
+        .journal
+        .into_iter()
+        .filter_map(|entry| {
+            let subject = match entry.subject {
+                SavedSubject::Marker(name) => Subject::Marker(Marker::parse(&name)?),
+                SavedSubject::Item(name) => Subject::Item(name),
+            };
+            Some(JournalEntry {
+                subject,
+                text: entry.text,
+                discovered_at: entry.discovered_at,
+                times_observed: entry.times_observed,
+            })
+        })
+        .collect();
+    pending.entities = save.entities;
+
+    for entity in world_item_query.iter() {
+        commands.despawn_recursive(entity);
+    }
+    spawn_world_item_records(
+        commands,
+        &save.world_items,
+        quick_save.world_item_z,
+        &items,
+        &item_assets,
+    );
+    captions.send(Caption("Game loaded".into()));
+}
+
+/// Puts saved state onto the entities it belongs to, as soon as they exist.
+fn apply_load_system(
+    commands: &mut Commands,
+    mut pending: ResMut<PendingLoad>,
+    mut bodies: ResMut<RigidBodySet>,
+    item_assets: Res<Assets<Item>>,
+    mut query: Query<
+        (
+            Entity,
+            Option<&String>,
+            Option<&PlayerId>,
+            Option<&Tile>,
+            Mut<Transform>,
+            Option<&RigidBodyHandleComponent>,
+            Option<Mut<Inventory>>,
+            Option<Mut<OvenState>>,
+            Option<Mut<Openable>>,
+        ),
+        With<Saved>,
+    >,
+    children: Query<&Children>,
+    colliders: Query<&ColliderHandleComponent>,
+    mut sprites: Query<Mut<TextureAtlasSprite>>,
+    spawners: Query<&TileMapSpawner>,
+    tiles: Query<&Tile>,
+) {
+    if pending.entities.is_empty() {
+        return;
+    }
+
+    // Players may still join, anything else missing once the tilemap spawned is gone.
+    let spawned = spawners.iter().next().is_none() || tiles.iter().next().is_some();
+
+    for (entity, name, player, tile, mut trans, body, inventory, oven, openable) in query.iter_mut()
+    {
+        let key = match SaveKey::of(name, player, tile) {
+            Some(key) => key,
+            None => continue,
+        };
+        let index = match pending.entities.iter().position(|it| it.key == key) {
+            Some(index) => index,
+            None => continue,
+        };
+        let saved = pending.entities.remove(index);
+        let items = |names: &[String]| -> Vec<Handle<Item>> {
+            names
+                .iter()
+                .filter_map(|it| item_by_name(&item_assets, it))
+                .collect()
+        };
+
+        if let Some((x, y)) = saved.position {
+            trans.translation.x = x;
+            trans.translation.y = y;
+            if let Some(body) = body.and_then(|it| bodies.get_mut(it.handle())) {
+                body.set_position(Isometry::translation(x, y), true);
+            }
+        }
+        if let (Some(mut inventory), Some(names)) = (inventory, &saved.inventory) {
+            inventory.items = items(names);
+        }
+        if let (Some(mut oven), Some(saved_oven)) = (oven, &saved.oven) {
+            let repeating = oven.baking_timer.repeating();
+            oven.baking_timer = Timer::from_seconds(saved_oven.duration, repeating);
+            oven.baking_timer.set_elapsed(saved_oven.elapsed);
+            oven.baking_timer.tick(0.0);
+            oven.item = saved_oven
+                .item
+                .as_ref()
+                .and_then(|it| item_by_name(&item_assets, it));
+            oven.baked_item = saved_oven
+                .baked_item
+                .as_ref()
+                .and_then(|it| item_by_name(&item_assets, it));
+            oven.on_fire = saved_oven.on_fire;
+        }
+        if let (Some(mut openable), Some(open)) = (openable, saved.open) {
+            if openable.open != open {
+                openable.open = open;
+                show_openable(
+                    commands,
+                    entity,
+                    &openable,
+                    &children,
+                    &colliders,
+                    &mut sprites,
+                );
+            }
+        }
+    }
+
+    if spawned {
+        pending
+            .entities
+            .retain(|it| matches!(it.key, SaveKey::Player(_)));
+    }
+}
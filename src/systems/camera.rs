@@ -0,0 +1,510 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    sprite::SpriteResizeMode,
+};
+
+use crate::{
+    commands_ext::CommandsExt,
+    input::{ActionState, InputAction, InputCapture},
+    levels::level2::{TileMap, TileMapSpawner},
+    replay::GameTime,
+};
+
+/// Moves cameras with a `CameraController`: follows the `CameraTarget`s through a deadzone,
+/// stays inside its bounds, zooms smoothly with the mouse wheel and snaps to whole pixels.
+/// The `ToggleFreeCamera` action switches to a debug free-fly mode steered with the
+/// `FreeCameraUp`, `FreeCameraDown`, `FreeCameraLeft` and `FreeCameraRight` actions,
+/// it captures the input meanwhile so the players stand still, see `InputCapture`.
+/// `CameraEffects` shake, flash and punch all cameras on top of that.
+/// Needs `GameTime`, see `ReplayPlugin`, and the `InputMapPlugin`.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CameraEffects>()
+            .add_system(camera_input_system.system())
+            .add_system(camera_follow_system.system())
+            .add_system(camera_flash_system.system())
+            .add_system_to_stage(stage::POST_UPDATE, camera_effects_decay_system.system());
+    }
+}
+
+#[test]
+fn follow_deadzone_and_bounds() {
+    let deadzone = Vec2::new(16.0, 8.0);
+    assert_eq!(
+        follow_deadzone(Vec2::zero(), Vec2::new(10.0, 4.0), deadzone),
+        Vec2::zero()
+    );
+    assert_eq!(
+        follow_deadzone(Vec2::zero(), Vec2::new(20.0, -12.0), deadzone),
+        Vec2::new(4.0, -4.0)
+    );
+
+    let bounds = CameraBounds {
+        min: Vec2::new(0.0, 0.0),
+        max: Vec2::new(100.0, 50.0),
+    };
+    let half_view = Vec2::new(20.0, 40.0);
+    assert_eq!(
+        bounds.clamp(Vec2::new(-5.0, 10.0), half_view),
+        Vec2::new(20.0, 25.0)
+    );
+    assert_eq!(
+        bounds.clamp(Vec2::new(90.0, 10.0), half_view),
+        Vec2::new(80.0, 25.0)
+    );
+
+    assert_eq!(snap_zoom(0.3, 1.0), 1.0 / 3.0);
+    assert_eq!(snap_zoom(1.4, 1.0), 1.0);
+    assert_eq!(
+        snap_to_pixel(Vec2::new(1.3, -0.4), 0.5),
+        Vec2::new(1.5, -0.5)
+    );
+}
+
+#[test]
+fn camera_effects_wear_off() {
+    let mut effects = CameraEffects::default();
+    assert_eq!(effects.shake_offset(1.0), Vec2::zero());
+
+    effects.add_trauma(0.7);
+    effects.add_trauma(0.7);
+    assert_eq!(effects.trauma(), 1.0);
+    effects.zoom_punch(0.2, 0.5);
+    assert_eq!(effects.zoom_scale(), 0.8);
+
+    effects.tick(0.5);
+    assert_eq!(effects.trauma(), 1.0 - 0.5 * TRAUMA_DECAY);
+    assert_eq!(effects.zoom_scale(), 1.0);
+
+    effects.tick(10.0);
+    assert_eq!(effects.trauma(), 0.0);
+    assert_eq!(effects.shake_offset(1.0), Vec2::zero());
+}
+
+/// Each mouse wheel line zooms by this factor.
+const ZOOM_STEP: f32 = 1.1;
+/// Pixel deltas of touch pads count as this many lines.
+const PIXELS_PER_LINE: f32 = 1.0 / 16.0;
+/// Screen pixels per second in free-fly mode.
+const FREE_FLY_SPEED: f32 = 400.0;
+/// Trauma lost per second.
+pub const TRAUMA_DECAY: f32 = 1.5;
+/// Screen pixels and radians of a shake at full trauma.
+const MAX_SHAKE_OFFSET: f32 = 12.0;
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+/// In front of everything, the 2d camera sees up to 1000 in front of it.
+const FLASH_Z: f32 = -1.0;
+
+/// Entities the cameras follow, several targets are followed by their middle.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CameraTarget;
+
+/// The area a camera may show, in world coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl CameraBounds {
+    /// Moves `center` so a view of `half_view` stays inside, centers it when the view is larger.
+    pub fn clamp(&self, center: Vec2, half_view: Vec2) -> Vec2 {
+        let clamp_axis = |center: f32, min: f32, max: f32, half: f32| {
+            if max - min <= 2.0 * half {
+                0.5 * (min + max)
+            } else {
+                center.max(min + half).min(max - half)
+            }
+        };
+        Vec2::new(
+            clamp_axis(center.x, self.min.x, self.max.x, half_view.x),
+            clamp_axis(center.y, self.min.y, self.max.y, half_view.y),
+        )
+    }
+}
+
+/// Drives the `Transform` of a camera, which is rewritten every frame from `center` and `zoom`.
+pub struct CameraController {
+    /// Half size of the rectangle around the center the targets move in freely.
+    pub deadzone: Vec2,
+    /// How fast the camera catches up with its targets and zoom, per second.
+    pub smoothing: f32,
+    pub bounds: Option<CameraBounds>,
+    /// Zooms out until all targets fit with this margin around them.
+    pub fit_margin: Option<f32>,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Size of one art pixel in world units, zoom and position are snapped to whole
+    /// screen pixels when set.
+    pub pixel_snap: Option<f32>,
+    pub free_fly: bool,
+    /// World units per screen pixel, the scale of the camera.
+    pub zoom: f32,
+    /// The zoom the camera eases into, changed by the mouse wheel.
+    pub target_zoom: f32,
+    pub center: Vec2,
+}
+
+impl CameraController {
+    pub fn new(zoom: f32) -> Self {
+        Self {
+            deadzone: Vec2::new(32.0, 24.0),
+            smoothing: 8.0,
+            bounds: None,
+            fit_margin: None,
+            min_zoom: zoom * 0.25,
+            max_zoom: zoom * 4.0,
+            pixel_snap: None,
+            free_fly: false,
+            zoom,
+            target_zoom: zoom,
+            center: Vec2::zero(),
+        }
+    }
+
+    /// Snaps to the pixels of art with `texel` world units per pixel, like 1.0 for the bitpack.
+    pub fn pixel_perfect(mut self, texel: f32) -> Self {
+        self.pixel_snap = Some(texel);
+        self
+    }
+
+    /// Zooms out to show all targets, with `margin` around them.
+    pub fn fit_targets(mut self, margin: f32) -> Self {
+        self.fit_margin = Some(margin);
+        self
+    }
+
+    /// The camera bundle for this controller.
+    pub fn bundle(self) -> (Camera2dBundle, Self) {
+        let mut bundle = Camera2dBundle::default();
+        bundle.transform.scale = Vec3::new(self.zoom, self.zoom, 1.0);
+        (bundle, self)
+    }
+}
+
+/// Shake, flash and zoom punch added on top of the controlled camera transforms,
+/// so they wear off without moving the cameras away from their targets.
+/// Any system can add to them.
+#[derive(Debug, Clone, Default)]
+pub struct CameraEffects {
+    /// From 0 to 1, shakes with its square so small hits stay subtle.
+    trauma: f32,
+    flash: Option<Fading<Color>>,
+    punch: Option<Fading<f32>>,
+    time: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fading<T> {
+    value: T,
+    duration: f32,
+    left: f32,
+}
+
+impl<T> Fading<T> {
+    fn new(value: T, duration: f32) -> Self {
+        Self {
+            value,
+            duration,
+            left: duration,
+        }
+    }
+
+    /// 1 when it started, 0 when it is over.
+    fn strength(&self) -> f32 {
+        if self.duration > 0.0 {
+            (self.left / self.duration).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl CameraEffects {
+    /// Shakes harder, trauma adds up to 1 and decays by `TRAUMA_DECAY` per second.
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0).max(0.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Tints the screen with `color`, fading out over `seconds`.
+    pub fn flash(&mut self, color: Color, seconds: f32) {
+        self.flash = Some(Fading::new(color, seconds));
+    }
+
+    /// Zooms in by `amount` of the view, easing back over `seconds`.
+    pub fn zoom_punch(&mut self, amount: f32, seconds: f32) {
+        self.punch = Some(Fading::new(amount, seconds));
+    }
+
+    /// Lets the effects wear off.
+    pub fn tick(&mut self, seconds: f32) {
+        self.time += seconds;
+        self.trauma = (self.trauma - TRAUMA_DECAY * seconds).max(0.0);
+        for flash in self.flash.as_mut() {
+            flash.left -= seconds;
+        }
+        for punch in self.punch.as_mut() {
+            punch.left -= seconds;
+        }
+        if self.flash.map_or(false, |it| it.left <= 0.0) {
+            self.flash = None;
+        }
+        if self.punch.map_or(false, |it| it.left <= 0.0) {
+            self.punch = None;
+        }
+    }
+
+    /// The shake offset in world units for a camera of `zoom`.
+    pub fn shake_offset(&self, zoom: f32) -> Vec2 {
+        let shake = self.trauma * self.trauma;
+        Vec2::new(shake_noise(self.time, 0.0), shake_noise(self.time, 10.0))
+            * (shake * MAX_SHAKE_OFFSET * zoom)
+    }
+
+    pub fn shake_angle(&self) -> f32 {
+        self.trauma * self.trauma * MAX_SHAKE_ANGLE * shake_noise(self.time, 20.0)
+    }
+
+    /// The factor for the camera scale, below 1 while punching in.
+    pub fn zoom_scale(&self) -> f32 {
+        self.punch
+            .map_or(1.0, |punch| 1.0 - punch.value * punch.strength())
+    }
+
+    /// The tint on top of the screen, transparent without a flash.
+    pub fn tint(&self) -> Color {
+        match self.flash {
+            Some(flash) => {
+                let mut color = flash.value;
+                color.set_a(color.a() * flash.strength());
+                color
+            }
+            None => Color::rgba(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Smooth noise from -1 to 1, different for every `seed`.
+fn shake_noise(time: f32, seed: f32) -> f32 {
+    let t = time + seed;
+    0.5 * ((t * 31.0).sin() + (t * 17.3 + 1.7).sin() * (t * 7.1).cos())
+}
+
+/// Moves `center` as little as possible to get `target` into the `deadzone` around it.
+pub fn follow_deadzone(center: Vec2, target: Vec2, deadzone: Vec2) -> Vec2 {
+    let offset = target - center;
+    let outside = offset - offset.max(-deadzone).min(deadzone);
+    center + outside
+}
+
+/// The closest zoom at which one art pixel of `texel` world units covers whole screen pixels.
+pub fn snap_zoom(zoom: f32, texel: f32) -> f32 {
+    if zoom < texel {
+        texel / (texel / zoom).round()
+    } else {
+        (zoom / texel).round() * texel
+    }
+}
+
+/// Rounds `pos` to whole screen pixels of `zoom` world units.
+pub fn snap_to_pixel(pos: Vec2, zoom: f32) -> Vec2 {
+    (pos / zoom).round() * zoom
+}
+
+/// Clamps the cameras to the tiles of the first loaded tilemap.
+/// Needs the `TileMap` asset, so levels with a tilemap add it themselves.
+pub fn tilemap_bounds_system(
+    tilemaps: Res<Assets<TileMap>>,
+    spawners: Query<(&TileMapSpawner, &GlobalTransform)>,
+    mut cameras: Query<Mut<CameraController>>,
+) {
+    let bounds = spawners.iter().find_map(|(spawner, trans)| {
+        let tilemap = tilemaps.get(spawner.handle())?;
+        let (min, max) = spawner.world_bounds(tilemap)?;
+        let origin = trans.translation.truncate();
+        Some(CameraBounds {
+            min: origin + min,
+            max: origin + max,
+        })
+    });
+
+    for mut controller in cameras.iter_mut() {
+        if controller.bounds != bounds {
+            controller.bounds = bounds;
+        }
+    }
+}
+
+/// Kept while the free camera captures the input.
+const FREE_CAMERA_ACTIONS: [InputAction; 5] = [
+    InputAction::ToggleFreeCamera,
+    InputAction::FreeCameraUp,
+    InputAction::FreeCameraDown,
+    InputAction::FreeCameraLeft,
+    InputAction::FreeCameraRight,
+];
+
+fn camera_input_system(
+    time: Res<GameTime>,
+    actions: Res<ActionState>,
+    mut capture: ResMut<InputCapture>,
+    mut wheel_reader: Local<EventReader<MouseWheel>>,
+    wheel_events: Res<Events<MouseWheel>>,
+    mut cameras: Query<Mut<CameraController>>,
+) {
+    let lines: f32 = wheel_reader
+        .iter(&wheel_events)
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y * PIXELS_PER_LINE,
+        })
+        .sum();
+
+    for mut controller in cameras.iter_mut() {
+        if lines != 0.0 {
+            let zoom = controller.target_zoom * ZOOM_STEP.powf(-lines);
+            controller.target_zoom = zoom.max(controller.min_zoom).min(controller.max_zoom);
+        }
+
+        if actions.just_pressed(InputAction::ToggleFreeCamera) {
+            controller.free_fly = !controller.free_fly;
+            capture.active = controller.free_fly;
+            capture.keep = FREE_CAMERA_ACTIONS.to_vec();
+        }
+        if controller.free_fly {
+            let dir = Vec2::new(
+                actions.axis(InputAction::FreeCameraLeft, InputAction::FreeCameraRight),
+                actions.axis(InputAction::FreeCameraDown, InputAction::FreeCameraUp),
+            );
+            let speed = FREE_FLY_SPEED * controller.zoom * time.delta_seconds();
+            controller.center += dir * speed;
+        }
+    }
+}
+
+/// Moves the center and zoom towards the targets and writes them to the camera transform,
+/// with the `CameraEffects` on top.
+fn camera_follow_system(
+    time: Res<GameTime>,
+    windows: Res<Windows>,
+    effects: Res<CameraEffects>,
+    targets: Query<&GlobalTransform, With<CameraTarget>>,
+    mut cameras: Query<(Mut<CameraController>, Mut<Transform>)>,
+) {
+    let window_size = windows
+        .get_primary()
+        .map(|window| Vec2::new(window.width(), window.height()))
+        .unwrap_or_default();
+    let dt = time.delta_seconds();
+
+    let mut positions = targets.iter().map(|trans| trans.translation.truncate());
+    let spread = positions.next().map(|first| {
+        positions.fold((first, first), |(min, max), pos| {
+            (min.min(pos), max.max(pos))
+        })
+    });
+
+    for (mut controller, mut trans) in cameras.iter_mut() {
+        let ease = 1.0 - (-controller.smoothing * dt).exp();
+
+        let mut zoom = controller.target_zoom;
+        if let (Some(margin), Some((min, max))) = (controller.fit_margin, spread) {
+            if window_size != Vec2::zero() {
+                let needed = (max - min + Vec2::splat(2.0 * margin)) / window_size;
+                zoom = zoom.max(needed.max_element());
+            }
+        }
+        controller.zoom += (zoom - controller.zoom) * ease;
+
+        if !controller.free_fly {
+            if let Some((min, max)) = spread {
+                let target = 0.5 * (min + max);
+                let wanted = follow_deadzone(controller.center, target, controller.deadzone);
+                controller.center = controller.center.lerp(wanted, ease);
+            }
+            if let Some(bounds) = controller.bounds {
+                let half_view = 0.5 * window_size * controller.zoom;
+                controller.center = bounds.clamp(controller.center, half_view);
+            }
+        }
+
+        let (center, zoom) = match controller.pixel_snap {
+            Some(texel) => {
+                let zoom = snap_zoom(controller.zoom, texel);
+                (snap_to_pixel(controller.center, zoom), zoom)
+            }
+            None => (controller.center, controller.zoom),
+        };
+        let center = center + snap_to_pixel(effects.shake_offset(zoom), zoom);
+        let zoom = zoom * effects.zoom_scale();
+        trans.translation.x = center.x;
+        trans.translation.y = center.y;
+        trans.rotation = Quat::from_rotation_z(effects.shake_angle());
+        trans.scale = Vec3::new(zoom, zoom, 1.0);
+    }
+}
+
+fn camera_effects_decay_system(time: Res<GameTime>, mut effects: ResMut<CameraEffects>) {
+    effects.tick(time.delta_seconds());
+}
+
+/// The sprite in front of a camera that shows the flash.
+struct CameraFlash {
+    sprite: Entity,
+    material: Handle<ColorMaterial>,
+}
+
+/// Covers the window of every camera with a sprite tinted by the current flash.
+fn camera_flash_system(
+    commands: &mut Commands,
+    windows: Res<Windows>,
+    effects: Res<CameraEffects>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    cameras: Query<(Entity, Option<&CameraFlash>), With<CameraController>>,
+    mut sprites: Query<Mut<Sprite>>,
+) {
+    let window_size = windows
+        .get_primary()
+        .map(|window| Vec2::new(window.width(), window.height()))
+        .unwrap_or_default();
+    let tint = effects.tint();
+
+    for (camera, flash) in cameras.iter() {
+        let flash = match flash {
+            Some(flash) => flash,
+            None => {
+                let material = materials.add(tint.into());
+                let sprite = commands.entity(SpriteBundle {
+                    sprite: Sprite {
+                        size: window_size,
+                        resize_mode: SpriteResizeMode::Manual,
+                    },
+                    material: material.clone(),
+                    transform: Transform::from_translation(Vec3::new(0.0, 0.0, FLASH_Z)),
+                    ..Default::default()
+                });
+                commands.push_children(camera, &[sprite]);
+                commands.insert_one(camera, CameraFlash { sprite, material });
+                continue;
+            }
+        };
+
+        for mut sprite in sprites.get_mut(flash.sprite) {
+            if sprite.size != window_size {
+                sprite.size = window_size;
+            }
+        }
+        for material in materials.get_mut(&flash.material) {
+            if material.color != tint {
+                material.color = tint;
+            }
+        }
+    }
+}
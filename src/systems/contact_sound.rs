@@ -0,0 +1,371 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{bevy_rapier_utils::*, systems::height::Landed};
+
+/// Plays the sounds of `SoundOnContact` when things hit walls or land after a throw,
+/// louder the faster they were.
+/// Wall contacts come from rapier, so it needs `RapierPhysicsPlugin`,
+/// landings come from `MovementPlugin`.
+pub struct ContactSoundPlugin {
+    pub backend: SoundBackend,
+}
+
+/// Where `PlaySound` events end up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundBackend {
+    /// Plays the sound assets with bevy audio.
+    Audio,
+    /// Plays nothing, but records into `HeadlessAudio`, for tests and machines without audio.
+    Headless,
+}
+
+impl Default for ContactSoundPlugin {
+    fn default() -> Self {
+        Self {
+            backend: SoundBackend::Audio,
+        }
+    }
+}
+
+impl ContactSoundPlugin {
+    pub fn headless() -> Self {
+        Self {
+            backend: SoundBackend::Headless,
+        }
+    }
+}
+
+impl Plugin for ContactSoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<SoundContact>()
+            .add_event::<PlaySound>()
+            .add_system(classify_contacts_system.system())
+            .add_system(landing_contact_system.system())
+            .add_system(contact_sound_system.system());
+
+        match self.backend {
+            SoundBackend::Audio => app
+                .add_startup_system(load_contact_sounds.system())
+                .add_system(audio_backend_system.system()),
+            SoundBackend::Headless => app
+                .init_resource::<HeadlessAudio>()
+                .add_system(headless_backend_system.system()),
+        };
+    }
+}
+
+#[test]
+fn headless_backend_records_sounds() {
+    let mut builder = App::build();
+    builder
+        .add_plugin(bevy::core::CorePlugin)
+        .add_event::<Landed>()
+        .add_plugin(ContactSoundPlugin::headless());
+    builder.resources_mut().insert(EventQueue::new(true));
+    builder.resources_mut().insert(ColliderSet::new());
+    builder.resources_mut().insert(RigidBodySet::new());
+    let mut app = builder.app;
+
+    let stone = app.world.spawn((SoundOnContact::new(vec![
+        (ContactType::Ground, SoundType::Clonk),
+        (ContactType::Wall, SoundType::Bling),
+    ]),));
+    let mut contacts = app.resources.get_mut::<Events<SoundContact>>().unwrap();
+    contacts.send(SoundContact {
+        entity: stone,
+        contact: ContactType::Wall,
+        speed: FULL_VOLUME_SPEED * 0.5,
+    });
+    contacts.send(SoundContact {
+        entity: stone,
+        contact: ContactType::Ground,
+        speed: 0.0,
+    });
+    drop(contacts);
+
+    app.update();
+    app.update();
+
+    let audio = app.resources.get::<HeadlessAudio>().unwrap();
+    assert_eq!(
+        audio.played,
+        vec![PlaySound {
+            sound: SoundType::Bling,
+            volume: 0.5
+        }]
+    );
+}
+
+#[test]
+fn scale_wav_samples() {
+    let samples: &[i16] = &[1000, -2000, 32767];
+    let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+    wav.extend_from_slice(&[1, 0, 1, 0, 0x22, 0x56, 0, 0, 0x44, 0xac, 0, 0, 2, 0, 16, 0]);
+    wav.extend_from_slice(b"data\x06\0\0\0");
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    let scaled = scale_wav(&wav, 0.5).unwrap();
+    let data = &scaled[scaled.len() - 6..];
+    let scaled: Vec<i16> = data
+        .chunks(2)
+        .map(|it| i16::from_le_bytes([it[0], it[1]]))
+        .collect();
+    assert_eq!(scaled, vec![500, -1000, 16384]);
+    assert_eq!(scale_wav(b"not a wav", 0.5), None);
+}
+
+/// Contacts at this speed play at full volume.
+pub const FULL_VOLUME_SPEED: f32 = 200.0;
+/// Quieter contacts are not worth a sound.
+pub const MIN_VOLUME: f32 = 0.05;
+/// Volumes are rounded up to this many steps, each with its own scaled copy of the sound.
+const VOLUME_LEVELS: u8 = 4;
+
+/// Which sound to play for which kind of contact.
+pub struct SoundOnContact {
+    pub sound_map: Vec<(ContactType, SoundType)>,
+}
+
+impl SoundOnContact {
+    pub fn new(sound_map: Vec<(ContactType, SoundType)>) -> Self {
+        Self { sound_map }
+    }
+
+    pub fn sound_for(&self, contact: ContactType) -> Option<SoundType> {
+        self.sound_map
+            .iter()
+            .find(|(it, _)| *it == contact)
+            .map(|(_, sound)| *sound)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContactType {
+    Ground,
+    Wall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundType {
+    Clonk,
+    Bling,
+}
+
+impl SoundType {
+    pub fn path(&self) -> &'static str {
+        match self {
+            SoundType::Clonk => "sounds/clonk.wav",
+            SoundType::Bling => "sounds/bling.wav",
+        }
+    }
+}
+
+/// `entity` hit something with `speed`.
+#[derive(Debug, Clone, Copy)]
+pub struct SoundContact {
+    pub entity: Entity,
+    pub contact: ContactType,
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaySound {
+    pub sound: SoundType,
+    /// From `MIN_VOLUME` to 1.
+    pub volume: f32,
+}
+
+/// Sounds the headless backend would have played.
+#[derive(Debug, Default)]
+pub struct HeadlessAudio {
+    pub played: Vec<PlaySound>,
+}
+
+struct ContactSounds {
+    sources: HashMap<SoundType, Handle<AudioSource>>,
+    scaled: HashMap<(SoundType, u8), Handle<AudioSource>>,
+}
+
+/// Turns rapier contacts of `SoundOnContact` things with static bodies into wall contacts.
+pub fn classify_contacts_system(
+    rapier_events: Res<EventQueue>,
+    colliders: Res<ColliderSet>,
+    bodies: Res<RigidBodySet>,
+    sounding: Query<&SoundOnContact>,
+    mut contacts: ResMut<Events<SoundContact>>,
+) {
+    let get_body = |h| {
+        colliders
+            .get_parent(h)
+            .and_then(|p| Some((p, bodies.get(p)?)))
+    };
+
+    while let Ok(event) = rapier_events.contact_events.pop() {
+        let (c1, c2) = match event {
+            ContactEvent::Started(c1, c2) => (c1, c2),
+            ContactEvent::Stopped(_, _) => continue,
+        };
+
+        for &(this, other) in [(c1, c2), (c2, c1)].iter() {
+            let (handle, body, other_body) = match (get_body(this), get_body(other)) {
+                (Some((handle, body)), Some((_, other_body))) => (handle, body, other_body),
+                _ => continue,
+            };
+            let entity = match bodies.get_entity(handle) {
+                Some(entity) if sounding.get(entity).is_ok() => entity,
+                _ => continue,
+            };
+
+            if other_body.is_static() {
+                contacts.send(SoundContact {
+                    entity,
+                    contact: ContactType::Wall,
+                    speed: (body.linvel() - other_body.linvel()).magnitude(),
+                });
+            }
+        }
+    }
+}
+
+/// Landings of `SoundOnContact` things are ground contacts.
+pub fn landing_contact_system(
+    mut reader: Local<EventReader<Landed>>,
+    events: Res<Events<Landed>>,
+    mut contacts: ResMut<Events<SoundContact>>,
+) {
+    for landed in reader.iter(&events) {
+        contacts.send(SoundContact {
+            entity: landed.entity,
+            contact: ContactType::Ground,
+            speed: landed.speed,
+        });
+    }
+}
+
+pub fn contact_sound_system(
+    mut reader: Local<EventReader<SoundContact>>,
+    events: Res<Events<SoundContact>>,
+    sounding: Query<&SoundOnContact>,
+    mut sounds: ResMut<Events<PlaySound>>,
+) {
+    for contact in reader.iter(&events) {
+        let sound = sounding
+            .get(contact.entity)
+            .ok()
+            .and_then(|it| it.sound_for(contact.contact));
+        let volume = (contact.speed / FULL_VOLUME_SPEED).min(1.0);
+
+        if let Some(sound) = sound {
+            if volume >= MIN_VOLUME {
+                sounds.send(PlaySound { sound, volume });
+            }
+        }
+    }
+}
+
+fn load_contact_sounds(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    let sources = [SoundType::Clonk, SoundType::Bling]
+        .iter()
+        .map(|sound| (*sound, asset_server.load(sound.path())))
+        .collect();
+
+    commands.insert_resource(ContactSounds {
+        sources,
+        scaled: HashMap::default(),
+    });
+}
+
+/// Bevy audio has no volume, so quieter sounds play a copy with scaled samples.
+fn audio_backend_system(
+    audio: Res<Audio>,
+    mut sounds: ResMut<ContactSounds>,
+    mut sources: ResMut<Assets<AudioSource>>,
+    mut reader: Local<EventReader<PlaySound>>,
+    events: Res<Events<PlaySound>>,
+) {
+    for event in reader.iter(&events) {
+        let level = ((event.volume * VOLUME_LEVELS as f32).ceil() as u8).clamp(1, VOLUME_LEVELS);
+        let source = match sounds.sources.get(&event.sound) {
+            Some(source) => source.clone(),
+            None => continue,
+        };
+
+        let handle = if level == VOLUME_LEVELS {
+            Some(source)
+        } else if let Some(scaled) = sounds.scaled.get(&(event.sound, level)) {
+            Some(scaled.clone())
+        } else {
+            let volume = level as f32 / VOLUME_LEVELS as f32;
+            let scaled = sources
+                .get(&source)
+                .and_then(|it| scale_wav(&it.bytes, volume))
+                .map(|bytes| {
+                    sources.add(AudioSource {
+                        bytes: bytes.into(),
+                    })
+                });
+            for scaled in scaled.iter() {
+                sounds.scaled.insert((event.sound, level), scaled.clone());
+            }
+            scaled
+        };
+
+        // not loaded yet
+        if let Some(handle) = handle {
+            audio.play(handle);
+        }
+    }
+}
+
+fn headless_backend_system(
+    mut audio: ResMut<HeadlessAudio>,
+    mut reader: Local<EventReader<PlaySound>>,
+    events: Res<Events<PlaySound>>,
+) {
+    audio.played.extend(reader.iter(&events).cloned());
+}
+
+/// Copy of a 16 bit PCM wav with its samples scaled by `volume`.
+pub fn scale_wav(bytes: &[u8], volume: f32) -> Option<Vec<u8>> {
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let mut scaled = bytes.to_vec();
+    let mut bits_per_sample = None;
+    let mut offset = 12;
+
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes([
+            bytes[offset + 4],
+            bytes[offset + 5],
+            bytes[offset + 6],
+            bytes[offset + 7],
+        ]) as usize;
+        let start = offset + 8;
+        let end = (start + size).min(bytes.len());
+
+        match id {
+            b"fmt " => {
+                let format = bytes.get(start..start + 16)?;
+                bits_per_sample = Some(u16::from_le_bytes([format[14], format[15]]));
+            }
+            b"data" if bits_per_sample == Some(16) => {
+                for sample in scaled[start..end].chunks_exact_mut(2) {
+                    let value = i16::from_le_bytes([sample[0], sample[1]]) as f32 * volume;
+                    sample.copy_from_slice(&(value.round() as i16).to_le_bytes());
+                }
+                return Some(scaled);
+            }
+            _ => {}
+        }
+
+        // chunks are padded to an even size
+        offset = start + size + size % 2;
+    }
+
+    None
+}
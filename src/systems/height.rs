@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::{point, primitive, FillOptions, ShapeType, TessellationMode};
+
+use crate::{
+    bevy_rapier_utils::*, collision_layer::CollisionLayer, commands_ext::CommandsExt,
+    replay::GameTime,
+};
+
+/// Shows `Height` with a shadow on the ground and the sprite children lifted,
+/// and lets `HeightCollider`s fly over walls.
+/// The height itself is simulated by `MovementPlugin`.
+pub struct HeightPlugin;
+
+impl Plugin for HeightPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(height_shadow_added.system())
+            .add_system(height_sprite_system.system())
+            .add_system(height_collider_system.system());
+    }
+}
+
+/// Pulls everything with a `VerticalVelocity` down.
+pub const GRAVITY: f32 = 400.0;
+/// Things higher than this fly over walls.
+pub const WALL_HEIGHT: f32 = 12.0;
+/// Vertical speed of throws.
+pub const THROW_LIFT: f32 = 120.0;
+
+const SHADOW_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.3);
+const SHADOW_RADIUS_X: f32 = 3.0;
+const SHADOW_RADIUS_Y: f32 = 1.5;
+const SHADOW_SEGMENTS: usize = 12;
+
+/// Height above the ground, a separate axis for a top down world.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Height(pub f32);
+
+/// Speed along `Height`, removed when landing.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VerticalVelocity(pub f32);
+
+/// `entity` reached the ground with `speed`.
+#[derive(Debug, Clone, Copy)]
+pub struct Landed {
+    pub entity: Entity,
+    pub speed: f32,
+}
+
+/// The collider of a body with `Height`, spawned as a child that ignores walls
+/// while the body is higher than `WALL_HEIGHT`.
+pub struct HeightCollider {
+    pub collider: ColliderBuilder,
+    above_walls: Option<bool>,
+}
+
+impl HeightCollider {
+    pub fn new(collider: ColliderBuilder) -> Self {
+        Self {
+            collider,
+            above_walls: None,
+        }
+    }
+}
+
+struct HeightShadow(Entity);
+
+pub fn gravity_system(
+    commands: &mut Commands,
+    time: Res<GameTime>,
+    mut landed: ResMut<Events<Landed>>,
+    mut query: Query<(Entity, Mut<Height>, Mut<VerticalVelocity>)>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut height, mut vertical) in query.iter_mut() {
+        vertical.0 -= GRAVITY * dt;
+        height.0 += vertical.0 * dt;
+
+        if height.0 <= 0.0 {
+            height.0 = 0.0;
+            landed.send(Landed {
+                entity,
+                speed: -vertical.0,
+            });
+            commands.remove_one::<VerticalVelocity>(entity);
+        }
+    }
+}
+
+fn height_shadow_added(
+    commands: &mut Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    query: Query<Entity, Added<Height>>,
+) {
+    for entity in query.iter() {
+        let points = (0..SHADOW_SEGMENTS)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / SHADOW_SEGMENTS as f32;
+                point(SHADOW_RADIUS_X * angle.cos(), SHADOW_RADIUS_Y * angle.sin())
+            })
+            .collect();
+
+        let shadow = commands
+            .spawn(primitive(
+                materials.add(SHADOW_COLOR.into()),
+                &mut meshes,
+                ShapeType::Polyline {
+                    points,
+                    closed: true,
+                },
+                TessellationMode::Fill(&FillOptions::default()),
+                Vec3::new(0.0, 0.0, -0.1),
+            ))
+            .current_entity()
+            .unwrap();
+
+        commands.push_children(entity, &[shadow]);
+        commands.insert_one(entity, HeightShadow(shadow));
+    }
+}
+
+/// Lifts the sprite children by the height and shrinks the shadow.
+fn height_sprite_system(
+    query: Query<(&Height, &Children, Option<&HeightShadow>), Changed<Height>>,
+    mut sprites: Query<Mut<Transform>, With<TextureAtlasSprite>>,
+    mut shadows: Query<Mut<Transform>, Without<TextureAtlasSprite>>,
+) {
+    for (height, children, shadow) in query.iter() {
+        for child in children.iter() {
+            for mut trans in sprites.get_mut(*child) {
+                trans.translation.y = height.0;
+            }
+        }
+
+        for mut trans in shadow.and_then(|it| shadows.get_mut(it.0).ok()) {
+            let scale = (1.0 - height.0 / (4.0 * WALL_HEIGHT)).max(0.4);
+            trans.scale = Vec3::new(scale, scale, 1.0);
+        }
+    }
+}
+
+/// Colliders can not change their groups, so the collider child is replaced
+/// when crossing `WALL_HEIGHT`.
+fn height_collider_system(
+    commands: &mut Commands,
+    mut query: Query<(
+        Entity,
+        Mut<HeightCollider>,
+        Option<&Height>,
+        Option<&Children>,
+    )>,
+    colliders: Query<&ColliderHandleComponent>,
+) {
+    for (entity, mut height_collider, height, children) in query.iter_mut() {
+        let above_walls = height.map_or(false, |it| it.0 > WALL_HEIGHT);
+        if height_collider.above_walls == Some(above_walls) {
+            continue;
+        }
+        height_collider.above_walls = Some(above_walls);
+
+        for children in children {
+            for child in children.iter().filter(|it| colliders.get(**it).is_ok()) {
+                commands.despawn_recursive(*child);
+            }
+        }
+
+        let mut collider = height_collider
+            .collider
+            .clone()
+            .user_data(entity.to_user_data());
+        if above_walls {
+            let groups = collider.collision_groups;
+            let mask = (groups.0 as u16) & !CollisionLayer::Wall.bit();
+            collider =
+                collider.collision_groups(InteractionGroups::new((groups.0 >> 16) as u16, mask));
+        }
+
+        let child = commands.entity((collider,));
+        commands.push_children(entity, &[child]);
+    }
+}
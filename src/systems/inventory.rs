@@ -26,6 +26,33 @@ impl Inventory {
         self.items.iter().filter(|it| *it == item).count()
     }
 
+    pub fn get(&self, index: usize) -> Option<&ItemKind> {
+        self.items.get(index)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<ItemKind> {
+        (index < self.items.len()).then(|| self.items.remove(index))
+    }
+
+    /// Inserts at `index` or appends if `index` is past the last item.
+    pub fn insert(&mut self, index: usize, item: ItemKind) {
+        let index = index.min(self.items.len());
+        self.items.insert(index, item);
+    }
+
+    /// Swaps two items, or moves `from` to the end if `to` is past the last item.
+    pub fn move_item(&mut self, from: usize, to: usize) {
+        if from >= self.items.len() || from == to {
+            return;
+        }
+        if to < self.items.len() {
+            self.items.swap(from, to);
+        } else {
+            let item = self.items.remove(from);
+            self.items.push(item);
+        }
+    }
+
     fn pos(&self, item: &ItemKind) -> Option<usize> {
         self.items.iter().position(|it| it == item)
     }
@@ -69,6 +96,17 @@ impl Items {
             }),
         }
     }
+
+    pub fn all(&self) -> [&ItemKind; 2] {
+        [&self.fish, &self.baked_fish]
+    }
+
+    pub fn by_name(&self, assets: &Assets<Item>, name: &str) -> Option<ItemKind> {
+        self.all()
+            .iter()
+            .find(|kind| assets.get(*kind).map(|it| it.name) == Some(name))
+            .map(|kind| (*kind).clone())
+    }
 }
 
 pub type ItemKind = Handle<Item>;
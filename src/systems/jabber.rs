@@ -1,6 +1,11 @@
 use bevy::prelude::*;
 use rand::Rng;
 
+use crate::{
+    replay::{game_time_system, GameTime},
+    rng::GameRng,
+};
+
 const LINES_OF_GREAT_IMPORTANCE: [&'static str; 7] = [
     "This isn't just about you. It's about what's best for all of us.",
     "What are you gonna do?",
@@ -42,8 +47,12 @@ impl Jabbering {
     }
 }
 
-pub fn jabbering_system(time: Res<Time>, mut query: Query<(Mut<JabberingTimer>, Mut<Jabbering>)>) {
-    let mut rng = rand::thread_rng();
+pub fn jabbering_system(
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
+    mut query: Query<(Mut<JabberingTimer>, Mut<Jabbering>)>,
+) {
+    let rng = rng.stream("jabbering");
 
     for (mut timer, mut jabbering) in query.iter_mut() {
         if timer.0.tick(time.delta_seconds()).just_finished() {
@@ -140,7 +149,10 @@ pub fn rendered_jabbering_system(
 pub fn example() {
     App::build()
         .add_plugins(DefaultPlugins)
+        .init_resource::<GameRng>()
+        .init_resource::<GameTime>()
         .add_startup_system(example_setup.system())
+        .add_system_to_stage(stage::FIRST, game_time_system.system())
         .add_system(jabbering_system.system())
         .add_system_to_stage(stage::POST_UPDATE, print_jabbering_system.system())
         .add_system_to_stage(stage::UPDATE, rendered_jabbering_system.system())
@@ -1,6 +1,11 @@
+pub mod camera;
+pub mod contact_sound;
 pub mod context_map;
+pub mod height;
 pub mod inventory;
 pub mod jabber;
+pub mod movement;
+pub mod proximity;
 pub mod steering;
 pub mod texture_atlas_utils;
 
@@ -0,0 +1,326 @@
+use bevy::prelude::*;
+
+use crate::{
+    bevy_rapier_utils::*,
+    replay::GameTime,
+    systems::height::{gravity_system, Height, Landed, VerticalVelocity, THROW_LIFT},
+};
+
+/// Moves entities by `Velocity`, `Impulse` and `Drag`, the same whether they have
+/// a rapier body or not. Without a body the `Transform` is moved, with a body
+/// its velocity and damping are kept in sync, which needs `with_rapier`.
+/// Thrown things also fly up along `Height` and fall back down.
+/// Runs on `GameTime`, which is ticked by `ReplayPlugin`.
+pub struct MovementPlugin {
+    pub rapier: bool,
+}
+
+impl Default for MovementPlugin {
+    fn default() -> Self {
+        Self { rapier: false }
+    }
+}
+
+impl MovementPlugin {
+    pub fn with_rapier() -> Self {
+        Self { rapier: true }
+    }
+}
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameTime>()
+            .add_event::<CarryBroken>()
+            .add_event::<Landed>()
+            .add_system(carry_system.system())
+            .add_system(gravity_system.system())
+            .add_system(throw_system.system())
+            .add_system_to_stage(stage::POST_UPDATE, kinematic_system.system());
+
+        if self.rapier {
+            app.add_system_to_stage(stage::PRE_UPDATE, body_velocity_read_system.system())
+                .add_system_to_stage(stage::POST_UPDATE, body_movement_system.system());
+        }
+    }
+}
+
+#[test]
+fn kinematic_movement() {
+    let mut builder = App::build();
+    builder
+        .add_plugin(bevy::core::CorePlugin)
+        .add_plugin(MovementPlugin::default())
+        .add_system_to_stage(stage::FIRST, crate::replay::game_time_system.system());
+    builder
+        .resources_mut()
+        .insert(GameTime::new(Some(1.0 / 60.0)));
+    let mut app = builder.app;
+
+    let stone = app.world.spawn((
+        Transform::default(),
+        Velocity::default(),
+        Impulse(Vec2::new(10.0, 0.0)),
+        Drag(0.5),
+    ));
+    app.update();
+
+    let velocity = app.world.get::<Velocity>(stone).unwrap().0;
+    assert!(velocity.x > 0.0 && velocity.x <= 10.0);
+    assert_eq!(velocity.y, 0.0);
+    assert!(app.world.get::<Impulse>(stone).is_err());
+}
+
+/// Units per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Velocity(pub Vec2);
+
+/// A change of `Velocity` applied once, independent of the mass of a body.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Impulse(pub Vec2);
+
+/// The part of the velocity lost per second, like rapier's linear damping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drag(pub f32);
+
+/// Follows `owner` at `offset` like a kinematic target, pulled by `Velocity`
+/// so bodies still collide on the way. Once attached, it is dropped when holding
+/// it in place needs more than its `CarryLimit`, like when it got stuck on a wall.
+pub struct Carried {
+    pub owner: Entity,
+    pub offset: Transform,
+    attached: bool,
+}
+
+impl Carried {
+    pub fn new(owner: Entity, offset: Transform) -> Self {
+        Self {
+            owner,
+            offset,
+            attached: false,
+        }
+    }
+
+    /// Whether it reached `offset` once since it was picked up.
+    pub fn attached(&self) -> bool {
+        self.attached
+    }
+}
+
+/// The force a carrier can hold the item with, per unit of mass,
+/// as `Velocity` changes do not care about mass. Defaults to `CARRY_MAX_FORCE`.
+#[derive(Debug, Clone, Copy)]
+pub struct CarryLimit {
+    pub max_force: f32,
+}
+
+pub const CARRY_MAX_FORCE: f32 = 4000.0;
+/// Carried things closer than this to their target are attached.
+const ATTACH_DISTANCE: f32 = 2.0;
+
+/// Sent when a carried thing was dropped because it got stuck.
+#[derive(Debug, Clone, Copy)]
+pub struct CarryBroken {
+    pub owner: Entity,
+    pub item: Entity,
+}
+
+/// Becomes an `Impulse` of `vel` once, on top of the velocity of `owner`,
+/// and a `VerticalVelocity` of `THROW_LIFT`.
+pub struct Thrown {
+    pub vel: Vec3,
+    pub owner: Option<Entity>,
+}
+
+impl Thrown {
+    pub fn new(vel: Vec3) -> Self {
+        Self { vel, owner: None }
+    }
+
+    /// Thrown by `owner`, keeps its velocity.
+    pub fn by(owner: Entity, vel: Vec3) -> Self {
+        Self {
+            vel,
+            owner: Some(owner),
+        }
+    }
+}
+
+/// The body velocity that was last copied to `Velocity`,
+/// to tell changes made by gameplay apart from changes made by physics.
+struct SyncedVelocity(Vec2);
+
+/// Pulls carried things towards their target with at most their `CarryLimit`.
+pub fn carry_system(
+    commands: &mut Commands,
+    time: Res<GameTime>,
+    mut broken: ResMut<Events<CarryBroken>>,
+    mut query: Query<(
+        Entity,
+        Mut<Carried>,
+        &Transform,
+        Mut<Velocity>,
+        Option<&CarryLimit>,
+    )>,
+    owners: Query<(&Transform, Option<&Velocity>), Without<Carried>>,
+) {
+    let dt = time.delta_seconds();
+    if dt <= 0.0 {
+        return;
+    }
+
+    for (item, mut carried, trans, mut velocity, limit) in query.iter_mut() {
+        let (owner_trans, owner_velocity) = match owners.get(carried.owner) {
+            Ok(it) => it,
+            Err(_) => {
+                commands.remove_one::<Carried>(item);
+                continue;
+            }
+        };
+
+        let target = owner_trans.translation + carried.offset.translation;
+        let distance = (target - trans.translation).truncate();
+        let owner_velocity = owner_velocity.map(|it| it.0).unwrap_or_default();
+
+        // the change of velocity that would reach the target this frame
+        let change = owner_velocity + distance / dt - velocity.0;
+        let max_force = limit.map_or(CARRY_MAX_FORCE, |it| it.max_force);
+
+        if carried.attached && change.length() / dt > max_force {
+            commands.remove_one::<Carried>(item);
+            broken.send(CarryBroken {
+                owner: carried.owner,
+                item,
+            });
+            continue;
+        }
+
+        let max_change = max_force * dt;
+        velocity.0 += if change.length() > max_change {
+            change.normalize() * max_change
+        } else {
+            change
+        };
+        if !carried.attached && distance.length() < ATTACH_DISTANCE {
+            carried.attached = true;
+        }
+    }
+}
+
+/// Releases thrown things with the velocity of their owner plus the impulse.
+pub fn throw_system(
+    commands: &mut Commands,
+    mut query: Query<(Entity, &Thrown, Mut<Velocity>, Option<&Height>)>,
+    owners: Query<&Velocity, Without<Thrown>>,
+) {
+    for (entity, thrown, mut velocity, height) in query.iter_mut() {
+        for owner_velocity in thrown.owner.and_then(|it| owners.get(it).ok()) {
+            velocity.0 = owner_velocity.0;
+        }
+        let height = height.cloned().unwrap_or_default();
+        commands.insert(
+            entity,
+            (
+                Impulse(thrown.vel.truncate()),
+                height,
+                VerticalVelocity(THROW_LIFT),
+            ),
+        );
+        commands.remove_one::<Thrown>(entity);
+    }
+}
+
+/// Moves everything without a rapier body.
+pub fn kinematic_system(
+    commands: &mut Commands,
+    time: Res<GameTime>,
+    mut query: Query<
+        (
+            Entity,
+            Mut<Velocity>,
+            Option<&Impulse>,
+            Option<&Drag>,
+            Mut<Transform>,
+        ),
+        Without<RigidBodyHandleComponent>,
+    >,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut velocity, impulse, drag, mut trans) in query.iter_mut() {
+        if let Some(impulse) = impulse {
+            velocity.0 += impulse.0;
+            commands.remove_one::<Impulse>(entity);
+        }
+        if let Some(drag) = drag {
+            velocity.0 *= (1.0 - drag.0 * dt).max(0.0);
+        }
+        trans.translation += velocity.0.extend(0.0) * dt;
+    }
+}
+
+/// Copies the velocity of bodies to `Velocity` before gameplay systems run.
+fn body_velocity_read_system(
+    commands: &mut Commands,
+    bodies: Res<RigidBodySet>,
+    mut query: Query<(
+        Entity,
+        Mut<Velocity>,
+        Option<Mut<SyncedVelocity>>,
+        &RigidBodyHandleComponent,
+    )>,
+) {
+    for (entity, mut velocity, synced, handle) in query.iter_mut() {
+        if let Some(body) = bodies.get(handle.handle()) {
+            let linvel = Vec2::new(body.linvel().x, body.linvel().y);
+            if velocity.0 != linvel {
+                velocity.0 = linvel;
+            }
+            match synced {
+                Some(mut synced) => synced.0 = linvel,
+                None => {
+                    commands.insert_one(entity, SyncedVelocity(linvel));
+                }
+            }
+        }
+    }
+}
+
+/// Hands `Velocity` changes made by gameplay, `Impulse` and `Drag` to bodies.
+fn body_movement_system(
+    commands: &mut Commands,
+    mut bodies: ResMut<RigidBodySet>,
+    query: Query<(
+        Entity,
+        &Velocity,
+        Option<&SyncedVelocity>,
+        Option<&Impulse>,
+        Option<&Drag>,
+        &RigidBodyHandleComponent,
+    )>,
+) {
+    for (entity, velocity, synced, impulse, drag, handle) in query.iter() {
+        let body = match bodies.get_mut(handle.handle()) {
+            Some(body) => body,
+            None => continue,
+        };
+
+        let changed = synced.map_or(true, |it| it.0 != velocity.0);
+        let mut linvel = if changed {
+            velocity.0
+        } else {
+            Vec2::new(body.linvel().x, body.linvel().y)
+        };
+        if let Some(impulse) = impulse {
+            linvel += impulse.0;
+            commands.remove_one::<Impulse>(entity);
+        }
+        if changed || impulse.is_some() {
+            body.set_linvel(linvel.into_vector2(), linvel != Vec2::zero());
+        }
+
+        if let Some(drag) = drag {
+            if body.linear_damping != drag.0 {
+                body.linear_damping = drag.0;
+            }
+        }
+    }
+}
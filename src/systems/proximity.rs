@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+
+use crate::{
+    bevy_rapier_utils::*,
+    components::{Marker, ProximitySet},
+};
+
+/// Maintains the `ProximitySet` of every sensor and sends enter and exit events.
+pub struct ProximityPlugin;
+
+impl Plugin for ProximityPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ProximityEnter>()
+            .add_event::<ProximityExit>()
+            .add_system(manage_proximity_set.system())
+            .add_system(proximity_cleanup_system.system());
+    }
+}
+
+/// `other` started to intersect the sensor of `sensor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProximityEnter {
+    pub sensor: Entity,
+    pub other: Entity,
+}
+
+/// `other` left the sensor of `sensor` or was despawned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProximityExit {
+    pub sensor: Entity,
+    pub other: Entity,
+}
+
+/// Limits what a sensor with a `ProximitySet` takes in, empty filters let everything in.
+#[derive(Debug, Clone, Default)]
+pub struct ProximityFilter {
+    /// Only entities with one of these markers.
+    pub markers: Vec<Marker>,
+    /// Only colliders that are member of one of these collision groups,
+    /// see `CollisionLayer::bits`.
+    pub groups: Option<u16>,
+    /// Never this entity, like the one a sensor with its own body follows.
+    pub owner: Option<Entity>,
+}
+
+impl ProximityFilter {
+    pub fn accepts(&self, other: Entity, marker: Option<&Marker>, collider: &Collider) -> bool {
+        if self.owner == Some(other) {
+            return false;
+        }
+        let marker_ok =
+            self.markers.is_empty() || marker.map_or(false, |it| self.markers.contains(it));
+        let memberships = (collider.collision_groups().0 >> 16) as u16;
+        let groups_ok = self.groups.map_or(true, |groups| memberships & groups != 0);
+        marker_ok && groups_ok
+    }
+}
+
+/// Keeps the `ProximitySet` of sensor colliders in sync with rapier proximity events.
+/// The sensor collider's user_data points to the entity holding the set,
+/// the set holds the entities of the bodies of the other colliders.
+pub fn manage_proximity_set(
+    rapier_events: Res<EventQueue>,
+    colliders: Res<ColliderSet>,
+    bodies: Res<RigidBodySet>,
+    mut enter_events: ResMut<Events<ProximityEnter>>,
+    mut exit_events: ResMut<Events<ProximityExit>>,
+    mut proximity_q: Query<(Mut<ProximitySet>, Option<&ProximityFilter>)>,
+    markers: Query<&Marker>,
+) {
+    let get_parent_entity = |h| colliders.get_parent(h).and_then(|p| bodies.get_entity(p));
+
+    while let Ok(ProximityEvent {
+        collider1: c1,
+        collider2: c2,
+        new_status,
+        ..
+    }) = rapier_events.proximity_events.pop()
+    {
+        for &(sensor_collider, other_collider) in [(c1, c2), (c2, c1)].iter() {
+            let sensor = match colliders.get_entity(sensor_collider) {
+                Some(sensor) => sensor,
+                None => continue,
+            };
+            let (mut proximity, filter) = match proximity_q.get_mut(sensor) {
+                Ok(it) => it,
+                Err(_) => continue,
+            };
+            let (other, collider) = match (
+                get_parent_entity(other_collider),
+                colliders.get(other_collider),
+            ) {
+                (Some(other), Some(collider)) => (other, collider),
+                _ => continue,
+            };
+
+            match new_status {
+                Proximity::Intersecting => {
+                    let accepted = filter.map_or(true, |filter| {
+                        filter.accepts(other, markers.get(other).ok(), collider)
+                    });
+                    if accepted && proximity.insert(other) {
+                        enter_events.send(ProximityEnter { sensor, other });
+                    }
+                }
+                Proximity::WithinMargin => {}
+                Proximity::Disjoint => {
+                    if proximity.remove(&other) {
+                        exit_events.send(ProximityExit { sensor, other });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Removes despawned entities from all sets, rapier sends no event for them.
+pub fn proximity_cleanup_system(
+    mut exit_events: ResMut<Events<ProximityExit>>,
+    mut proximity_q: Query<(Entity, Mut<ProximitySet>)>,
+    bodies: Query<&RigidBodyHandleComponent>,
+) {
+    for (sensor, mut proximity) in proximity_q.iter_mut() {
+        let gone: Vec<Entity> = proximity
+            .iter()
+            .filter(|other| bodies.get(**other).is_err())
+            .cloned()
+            .collect();
+
+        for other in gone {
+            proximity.remove(&other);
+            exit_events.send(ProximityExit { sensor, other });
+        }
+    }
+}
@@ -16,7 +16,11 @@ use bevy_rapier2d::{
 };
 use rand::Rng;
 
-use crate::bevy_rapier_utils::IntoVector2;
+use crate::{
+    bevy_rapier_utils::IntoVector2,
+    replay::{game_time_system, GameTime},
+    rng::GameRng,
+};
 
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Default)]
 pub struct Boid {
@@ -63,9 +67,7 @@ impl FlockAverages {
 
 pub type Flocks = Vec<FlockParameters>;
 
-fn spawn_flocks(commands: &mut Commands, flocks: &Flocks) {
-    let mut rng = rand::thread_rng();
-
+fn spawn_flocks(commands: &mut Commands, flocks: &Flocks, rng: &mut impl Rng) {
     for flock in flocks.iter() {
         for index in 0..flock.boid_count {
             let pos = Vec2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
@@ -114,7 +116,7 @@ fn calculate_averages(
 }
 
 fn flocks_update_system(
-    time: Res<Time>,
+    time: Res<GameTime>,
     mut averages: Local<Vec<FlockAverages>>,
     flocks: ResMut<Flocks>,
     mut boids_q: Query<(Mut<Boid>, Mut<Transform>)>,
@@ -208,7 +210,7 @@ fn calculate_cohesion(&position: &Vec2, &average_position: &Vec2, flock_radius:
     }
 }
 
-pub fn boid_arcade_update_system(time: Res<Time>, mut boids_q: Query<(&Boid, Mut<Transform>)>) {
+pub fn boid_arcade_update_system(time: Res<GameTime>, mut boids_q: Query<(&Boid, Mut<Transform>)>) {
     for (boid, mut trans) in boids_q.iter_mut() {
         let vel = (boid.velocity * time.delta_seconds()).extend(0.0);
         trans.translation += vel;
@@ -257,7 +259,10 @@ fn boid_example_sprite_system(
 pub fn arcade_example() {
     App::build()
         .init_resource::<Flocks>()
+        .init_resource::<GameRng>()
+        .init_resource::<GameTime>()
         .add_plugins(DefaultPlugins)
+        .add_system_to_stage(stage::FIRST, game_time_system.system())
         .add_startup_system(example_setup.system())
         .add_system(flocks_update_system.system())
         .add_system(boid_arcade_update_system.system())
@@ -265,7 +270,7 @@ pub fn arcade_example() {
         .run();
 }
 
-fn example_setup(cmds: &mut Commands) {
+fn example_setup(cmds: &mut Commands, mut rng: ResMut<GameRng>) {
     cmds.spawn({
         let mut bundle = Camera2dBundle::default();
         bundle.transform.scale = Vec3::new(0.5, 0.5, 1.0);
@@ -283,7 +288,7 @@ fn example_setup(cmds: &mut Commands) {
         radius: 50.0,
     });
 
-    spawn_flocks(cmds, &flocks);
+    spawn_flocks(cmds, &flocks, rng.stream("flocks"));
     cmds.insert_resource(flocks);
 }
 
@@ -296,7 +301,10 @@ fn rapier_config(mut config: ResMut<RapierConfiguration>) {
 pub fn rapier_example() {
     App::build()
         .init_resource::<Flocks>()
+        .init_resource::<GameRng>()
+        .init_resource::<GameTime>()
         .add_plugins(DefaultPlugins)
+        .add_system_to_stage(stage::FIRST, game_time_system.system())
         .add_plugin(RapierPhysicsPlugin)
         .add_startup_system(example_setup.system())
         .add_startup_system(rapier_config.system())
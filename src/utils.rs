@@ -1,9 +1,40 @@
+use bevy::{
+    prelude::*,
+    render::{camera::Camera, render_graph::base::camera::CAMERA_2D},
+};
+use rand::Rng;
+
 pub trait SliceExt<T> {
-    fn random(&self) -> T;
+    fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> T;
 }
 
 impl<T: Copy> SliceExt<T> for [T] {
-    fn random(&self) -> T {
-        self[rand::random::<usize>() % self.len()]
+    fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> T {
+        self[rng.gen_range(0, self.len())]
     }
 }
+
+/// Converts a window cursor position into world coordinates as seen by `camera_transform`.
+pub fn screen_to_world(window: &Window, cursor: Vec2, camera_transform: &Transform) -> Vec2 {
+    let size = Vec2::new(window.width(), window.height());
+    let p = cursor - size * 0.5;
+    let pos_wld = camera_transform.compute_matrix() * p.extend(0.0).extend(1.0);
+    pos_wld.truncate().truncate()
+}
+
+/// Converts a world position into window coordinates, the inverse of `screen_to_world`.
+pub fn world_to_screen(window: &Window, world: Vec2, camera_transform: &Transform) -> Vec2 {
+    let size = Vec2::new(window.width(), window.height());
+    let p = camera_transform.compute_matrix().inverse() * world.extend(0.0).extend(1.0);
+    p.truncate().truncate() + size * 0.5
+}
+
+/// The transform of the first 2d camera, skipping the UI camera.
+pub fn camera_2d_transform<'a>(
+    camera_query: &'a Query<(&Camera, &Transform)>,
+) -> Option<&'a Transform> {
+    camera_query
+        .iter()
+        .find(|(camera, _)| camera.name.as_deref() == Some(CAMERA_2D))
+        .map(|(_, trans)| trans)
+}